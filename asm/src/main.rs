@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: chip8-asm [-o OUT] SOURCE
+
+Assembles SOURCE into a rom that can be run with main. Sources ending in .8o are
+compiled as Octo, anything else is read as assembly.
+
+options:
+    -o OUT    where to write the rom, SOURCE with a .ch8 extension by default";
+
+struct Options {
+    source: PathBuf,
+    output: PathBuf,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let is_octo = options.source.extension().is_some_and(|ext| ext == "8o");
+    let result = if is_octo {
+        chip8::compile_octo_file(&options.source)
+    } else {
+        chip8::assemble_file(&options.source)
+    };
+    let rom = result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+
+    if let Err(e) = fs::write(&options.output, &rom) {
+        eprintln!("Failed to write {}: {}", options.output.display(), e);
+        process::exit(1);
+    }
+    eprintln!("Wrote {} bytes to {}", rom.len(), options.output.display());
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut source = None;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().ok_or("-o needs a value")?)),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let source = source.ok_or("no source given")?;
+    let output = output.unwrap_or_else(|| source.with_extension("ch8"));
+    Ok(Options { source, output })
+}
@@ -0,0 +1,104 @@
+use chip8::DEFAULT_START_ADDRESS;
+use std::io::{self, Write};
+use std::{env, fs, process};
+
+const USAGE: &str = "usage: chip8-disasm [--origin ADDR] [--cfg | --calls] ROM
+
+Prints an annotated listing of ROM on stdout.
+
+options:
+    --origin ADDR    hex address the rom is loaded at, 200 by default (600 for ETI-660)
+    --cfg            print the basic blocks and their edges as a Graphviz graph instead
+    --calls          print which subroutines call which as a Graphviz graph instead";
+
+#[derive(PartialEq)]
+enum Output {
+    Listing,
+    ControlFlow,
+    Calls,
+}
+
+struct Options {
+    rom: String,
+    origin: u16,
+    output: Output,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let rom = fs::read(&options.rom).unwrap_or_else(|e| {
+        eprintln!("Failed to read {}: {}", options.rom, e);
+        process::exit(1);
+    });
+
+    // A closed pipe, as with `chip8-disasm ROM | head`, is not worth reporting
+    let mut stdout = io::stdout().lock();
+    let _ = match options.output {
+        Output::Listing => {
+            let listing = chip8::disassemble(&rom, options.origin);
+            write!(stdout, "; {} ({} bytes)\n{}", options.rom, rom.len(), listing)
+        }
+        Output::ControlFlow => write!(stdout, "{}", chip8::control_flow_graph(&rom, options.origin).to_dot()),
+        Output::Calls => write!(stdout, "{}", chip8::control_flow_graph(&rom, options.origin).call_graph_dot()),
+    };
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut rom = None;
+    let mut origin = DEFAULT_START_ADDRESS;
+    let mut output = Output::Listing;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => {
+                let value = args.next().ok_or("--origin needs a value")?;
+                origin = parse_address(&value).ok_or(format!("--origin expects an address, got '{}'", value))?;
+            }
+            "--cfg" | "--calls" if output != Output::Listing => return Err("--cfg and --calls can't be used together".to_string()),
+            "--cfg" => output = Output::ControlFlow,
+            "--calls" => output = Output::Calls,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => rom = Some(arg),
+        }
+    }
+
+    Ok(Options { rom: rom.ok_or("no rom given")?, origin, output })
+}
+
+// Addresses are hex with or without a prefix, the same as the emulator's --load-address
+fn parse_address(value: &str) -> Option<u16> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix('#')).unwrap_or(value);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_options(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn origin_should_be_read_as_hex_with_or_without_a_prefix() {
+        for value in ["600", "0x600", "#600"] {
+            assert_eq!(parse(&["--origin", value, "rom.ch8"]).unwrap().origin, 0x600);
+        }
+    }
+
+    #[test]
+    fn origin_should_default_to_the_start_address() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().origin, DEFAULT_START_ADDRESS);
+    }
+
+    #[test]
+    fn invalid_origins_should_be_rejected() {
+        assert!(parse(&["--origin", "xyz", "rom.ch8"]).is_err());
+        assert!(parse(&["--origin", "10000", "rom.ch8"]).is_err());
+        assert!(parse(&["rom.ch8", "--origin"]).is_err());
+    }
+}
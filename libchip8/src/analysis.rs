@@ -0,0 +1,344 @@
+use crate::disasm::{disassemble, Disassembly, Item};
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// Execution runs on into the next block.
+    Fallthrough,
+    /// A JP.
+    Jump,
+    /// A skip instruction's condition held and the next instruction was skipped.
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that always run in sequence: only the first is a branch target and only
+/// the last branches. CALL does not end a block, its target is listed in `calls`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    pub instructions: Vec<(u16, Instruction)>,
+    pub successors: Vec<Edge>,
+    pub calls: Vec<u16>,
+    /// Ends in BNNN, whose target depends on V0 and can't be followed statically.
+    pub unresolved: bool,
+}
+
+impl BasicBlock {
+    /// The address of the last instruction.
+    pub fn end(&self) -> u16 {
+        self.instructions.last().map_or(self.start, |(addr, _)| *addr)
+    }
+}
+
+/// The blocks reachable from a CALL target, or from the rom's start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Subroutine {
+    pub entry: u16,
+    pub blocks: Vec<u16>,
+    pub callees: BTreeSet<u16>,
+}
+
+/// The basic blocks and subroutines of a rom, found by following its control flow the same
+/// way `disassemble` does.
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph {
+    disassembly: Disassembly,
+    blocks: BTreeMap<u16, BasicBlock>,
+    subroutines: BTreeMap<u16, Subroutine>,
+}
+
+/// Splits `rom` loaded at `origin` into basic blocks and groups them into subroutines.
+pub fn control_flow_graph(rom: &[u8], origin: u16) -> ControlFlowGraph {
+    let disassembly = disassemble(rom, origin);
+    let code: BTreeMap<u16, Instruction> = disassembly
+        .lines()
+        .iter()
+        .filter_map(|line| match line.item {
+            Item::Code { instruction, .. } => Some((line.addr, instruction)),
+            Item::Data { .. } => None,
+        })
+        .collect();
+
+    // Every branch target and every instruction after a branch starts a block
+    let mut leaders = BTreeSet::from([origin]);
+    for (&addr, instruction) in &code {
+        let next = addr + instruction.size();
+        match *instruction {
+            Instruction::Jp(target) => {
+                leaders.insert(target);
+                leaders.insert(next);
+            }
+            Instruction::Call(target) => {
+                leaders.insert(target);
+            }
+            Instruction::JpV0(_) | Instruction::Ret | Instruction::Exit => {
+                leaders.insert(next);
+            }
+            _ if is_skip(instruction) => {
+                leaders.insert(next);
+                leaders.insert(next + skipped_size(&code, next));
+            }
+            _ => {}
+        }
+    }
+
+    let mut blocks: BTreeMap<u16, BasicBlock> = BTreeMap::new();
+    let mut current: Option<BasicBlock> = None;
+    for (&addr, &instruction) in &code {
+        let mut block = match current.take() {
+            Some(block) if !leaders.contains(&addr) && block.end() + block_last_size(&block) == addr => block,
+            previous => {
+                if let Some(block) = previous {
+                    blocks.insert(block.start, block);
+                }
+                BasicBlock { start: addr, instructions: Vec::new(), successors: Vec::new(), calls: Vec::new(), unresolved: false }
+            }
+        };
+        block.instructions.push((addr, instruction));
+        if let Instruction::Call(target) = instruction {
+            block.calls.push(target);
+        }
+        current = Some(block);
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+
+    for block in blocks.values_mut() {
+        let (addr, last) = *block.instructions.last().unwrap();
+        let next = addr + last.size();
+        let fallthrough = Edge { target: next, kind: EdgeKind::Fallthrough };
+        block.successors = match last {
+            Instruction::Jp(target) => vec![Edge { target, kind: EdgeKind::Jump }],
+            Instruction::JpV0(_) => {
+                block.unresolved = true;
+                Vec::new()
+            }
+            Instruction::Ret | Instruction::Exit => Vec::new(),
+            _ if is_skip(&last) => {
+                vec![fallthrough, Edge { target: next + skipped_size(&code, next), kind: EdgeKind::Skip }]
+            }
+            _ => vec![fallthrough],
+        };
+        // Code that runs off the end of the rom or into data has nowhere to go
+        block.successors.retain(|edge| edge.kind != EdgeKind::Fallthrough || code.contains_key(&edge.target));
+    }
+
+    let mut entries = BTreeSet::from([origin]);
+    entries.extend(blocks.values().flat_map(|block| block.calls.iter().copied()));
+    let subroutines = entries
+        .into_iter()
+        .filter(|entry| blocks.contains_key(entry))
+        .map(|entry| {
+            let mut seen = BTreeSet::new();
+            let mut pending = vec![entry];
+            while let Some(start) = pending.pop() {
+                let Some(block) = blocks.get(&start) else {
+                    continue;
+                };
+                if seen.insert(start) {
+                    pending.extend(block.successors.iter().map(|edge| edge.target));
+                }
+            }
+            let callees = seen.iter().flat_map(|start| blocks[start].calls.iter().copied()).collect();
+            (entry, Subroutine { entry, blocks: seen.into_iter().collect(), callees })
+        })
+        .collect();
+
+    ControlFlowGraph { disassembly, blocks, subroutines }
+}
+
+fn is_skip(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::SeVxByte { .. }
+            | Instruction::SneVxByte { .. }
+            | Instruction::SeVxVy { .. }
+            | Instruction::SneVxVy { .. }
+            | Instruction::SkpVx { .. }
+            | Instruction::SknpVx { .. }
+    )
+}
+
+// F000 NNNN is skipped as a whole
+fn skipped_size(code: &BTreeMap<u16, Instruction>, addr: u16) -> u16 {
+    code.get(&addr).map_or(2, Instruction::size)
+}
+
+fn block_last_size(block: &BasicBlock) -> u16 {
+    block.instructions.last().map_or(0, |(_, instruction)| instruction.size())
+}
+
+impl ControlFlowGraph {
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: u16) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    pub fn subroutines(&self) -> impl Iterator<Item = &Subroutine> {
+        self.subroutines.values()
+    }
+
+    /// Blocks ending in BNNN.
+    pub fn unresolved(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values().filter(|block| block.unresolved)
+    }
+
+    fn name(&self, addr: u16) -> String {
+        match self.disassembly.label(addr) {
+            Some(label) => label.to_string(),
+            None if addr == self.disassembly.origin() => "start".to_string(),
+            None => format!("#{:03X}", addr),
+        }
+    }
+
+    /// The basic blocks and their edges in Graphviz DOT. Skips are dashed, jumps bold and
+    /// blocks ending in an unresolved BNNN red.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for block in self.blocks.values() {
+            let mut label = format!("{}:\\l", self.name(block.start));
+            for (addr, instruction) in &block.instructions {
+                let text = instruction.mnemonic(|target| self.name(target));
+                let _ = write!(label, "{:03X}  {}\\l", addr, escape(&text));
+            }
+            let color = if block.unresolved { ", color=red" } else { "" };
+            let _ = writeln!(dot, "    b{:03X} [label=\"{}\"{}];", block.start, label, color);
+        }
+        for block in self.blocks.values() {
+            for edge in &block.successors {
+                let style = match edge.kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [style=bold]",
+                    EdgeKind::Skip => " [style=dashed]",
+                };
+                let _ = writeln!(dot, "    b{:03X} -> b{:03X}{};", block.start, edge.target, style);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Which subroutines call which in Graphviz DOT, starting from the rom's entry point.
+    pub fn call_graph_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n    node [shape=box, fontname=\"monospace\"];\n");
+        for subroutine in self.subroutines.values() {
+            let unresolved = subroutine.blocks.iter().any(|start| self.blocks[start].unresolved);
+            let color = if unresolved { ", color=red" } else { "" };
+            let _ = writeln!(dot, "    s{:03X} [label=\"{}\"{}];", subroutine.entry, self.name(subroutine.entry), color);
+        }
+        for subroutine in self.subroutines.values() {
+            for callee in &subroutine.callees {
+                let _ = writeln!(dot, "    s{:03X} -> s{:03X};", subroutine.entry, callee);
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: CALL 20A
+    // 202: SE V0, 1
+    // 204: JP 200
+    // 206: LD V0, 2
+    // 208: JP V0, 300
+    // 20A: ADD V0, 1
+    // 20C: CALL 210
+    // 20E: RET
+    // 210: RET
+    const ROM: [u8; 18] = [
+        0x22, 0x0A, 0x30, 0x01, 0x12, 0x00, 0x60, 0x02, 0xB3, 0x00, 0x70, 0x01, 0x22, 0x10, 0x00, 0xEE, 0x00, 0xEE,
+    ];
+
+    #[test]
+    fn blocks_should_split_at_branches_and_targets() {
+        let cfg = control_flow_graph(&ROM, 0x200);
+        let starts: Vec<u16> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, vec![0x200, 0x204, 0x206, 0x20A, 0x210]);
+
+        let first = cfg.block(0x200).unwrap();
+        assert_eq!(first.end(), 0x202);
+        assert_eq!(first.calls, vec![0x20A]);
+        assert_eq!(
+            first.successors,
+            vec![Edge { target: 0x204, kind: EdgeKind::Fallthrough }, Edge { target: 0x206, kind: EdgeKind::Skip }]
+        );
+        assert_eq!(cfg.block(0x204).unwrap().successors, vec![Edge { target: 0x200, kind: EdgeKind::Jump }]);
+        assert!(cfg.block(0x20A).unwrap().successors.is_empty());
+    }
+
+    #[test]
+    fn computed_jumps_should_be_flagged_unresolved() {
+        let cfg = control_flow_graph(&ROM, 0x200);
+        let unresolved: Vec<u16> = cfg.unresolved().map(|block| block.start).collect();
+        assert_eq!(unresolved, vec![0x206]);
+        assert!(cfg.block(0x206).unwrap().successors.is_empty());
+    }
+
+    #[test]
+    fn subroutines_should_collect_their_blocks_and_callees() {
+        let cfg = control_flow_graph(&ROM, 0x200);
+        let subroutines: Vec<(u16, Vec<u16>, Vec<u16>)> = cfg
+            .subroutines()
+            .map(|s| (s.entry, s.blocks.clone(), s.callees.iter().copied().collect()))
+            .collect();
+
+        assert_eq!(
+            subroutines,
+            vec![
+                (0x200, vec![0x200, 0x204, 0x206], vec![0x20A]),
+                (0x20A, vec![0x20A], vec![0x210]),
+                (0x210, vec![0x210], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn dot_output_should_describe_blocks_and_calls() {
+        let cfg = control_flow_graph(&ROM, 0x200);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains(r#"b200 [label="loc_200:\l200  CALL sub_20A\l202  SE V0, #01\l"];"#));
+        assert!(dot.contains("b200 -> b206 [style=dashed];"));
+        assert!(dot.contains("b204 -> b200 [style=bold];"));
+        assert!(dot.contains("b206 [label=\"#206:\\l206  LD V0, #02\\l208  JP V0, #300\\l\", color=red];"));
+
+        let calls = cfg.call_graph_dot();
+        assert!(calls.contains("s200 [label=\"loc_200\", color=red];"));
+        assert!(calls.contains("s200 -> s20A;"));
+        assert!(calls.contains("s20A -> s210;"));
+    }
+
+    #[test]
+    fn blinky_should_have_a_connected_call_graph() {
+        let cfg = control_flow_graph(include_bytes!("../../roms/BLINKY"), 0x200);
+
+        assert!(cfg.blocks().count() > 20);
+        let entry = cfg.subroutines().next().unwrap();
+        assert_eq!(entry.entry, 0x200);
+        assert!(!entry.callees.is_empty());
+        for subroutine in cfg.subroutines() {
+            assert!(subroutine.blocks.iter().all(|start| cfg.block(*start).is_some()));
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::platform::DEFAULT_START_ADDRESS;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Deep enough for any sensible layout, shallow enough to stop a file including itself
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Assembles source written with the mnemonics `Instruction::mnemonic` produces into a rom
+/// starting at the first ORG address, 0x200 unless the source says otherwise.
+///
+/// Besides instructions the source may contain `label:` definitions, `NAME EQU expr` or
+/// `NAME = expr` constants, `DB` bytes and strings, `DW` big endian words, `ORG addr`,
+/// `INCLUDE "file.asm"` and `INCBIN "file.bin"`. Numbers are decimal or prefixed with
+/// `#`, `$` or `0x` for hex and `%` or `0b` for binary, and can be added and subtracted.
+/// Included files are looked up relative to the current directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, Chip8Error> {
+    let mut assembler = Assembler::new();
+    assembler.read_source("<input>", source, Path::new(""), 0)?;
+    assembler.emit()
+}
+
+/// Assembles the file at `path`, looking up its includes relative to its own directory.
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Vec<u8>, Chip8Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    let mut assembler = Assembler::new();
+    assembler.read_source(&path.display().to_string(), &source, dir, 0)?;
+    assembler.emit()
+}
+
+#[derive(Clone)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, message: impl Into<String>) -> Chip8Error {
+        Chip8Error::Asm { file: self.file.clone(), line: self.line, message: message.into() }
+    }
+}
+
+enum Directive {
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Binary(Vec<u8>),
+}
+
+struct Statement {
+    location: Location,
+    addr: usize,
+    directive: Directive,
+}
+
+struct Assembler {
+    origin: Option<usize>,
+    addr: usize,
+    symbols: HashMap<String, i64>,
+    statements: Vec<Statement>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            origin: None,
+            addr: DEFAULT_START_ADDRESS as usize,
+            symbols: HashMap::new(),
+            statements: Vec::new(),
+        }
+    }
+
+    // First pass: splits every line into a statement and assigns addresses to labels
+    fn read_source(&mut self, file: &str, source: &str, dir: &Path, depth: usize) -> Result<(), Chip8Error> {
+        for (index, line) in source.lines().enumerate() {
+            let location = Location { file: file.to_string(), line: index + 1 };
+            let mut text = strip_comment(line).trim();
+
+            if let Some((label, rest)) = split_label(text) {
+                self.define(&location, label, self.addr as i64)?;
+                text = rest.trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (first, rest) = split_word(text);
+            let (second, value) = split_word(rest);
+            if second.eq_ignore_ascii_case("EQU") || second == "=" {
+                let value = self.eval(&location, value)?;
+                self.define(&location, first, value)?;
+                continue;
+            }
+
+            let mnemonic = first.to_ascii_uppercase();
+            let operands = split_operands(rest);
+            let directive = match mnemonic.as_str() {
+                "ORG" => {
+                    let [addr] = operands.as_slice() else {
+                        return Err(location.error("ORG takes one address"));
+                    };
+                    let addr = self.eval(&location, addr)? as usize;
+                    if self.origin.is_some() && addr < self.addr {
+                        return Err(location.error(format!("ORG {:#05X} is before the current address", addr)));
+                    }
+                    if self.statements.is_empty() {
+                        self.origin = Some(addr);
+                    }
+                    self.addr = addr;
+                    continue;
+                }
+                "INCLUDE" => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(location.error("includes are nested too deeply"));
+                    }
+                    let path = include_path(&location, dir, &operands)?;
+                    let source = fs::read_to_string(&path)
+                        .map_err(|e| location.error(format!("cannot include {}: {}", path.display(), e)))?;
+                    let include_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                    self.read_source(&path.display().to_string(), &source, &include_dir, depth + 1)?;
+                    continue;
+                }
+                "INCBIN" => {
+                    let path = include_path(&location, dir, &operands)?;
+                    let data = fs::read(&path)
+                        .map_err(|e| location.error(format!("cannot include {}: {}", path.display(), e)))?;
+                    Directive::Binary(data)
+                }
+                "DB" => Directive::Bytes(operands),
+                "DW" => Directive::Words(operands),
+                _ => Directive::Instruction { mnemonic, operands },
+            };
+
+            let size = match &directive {
+                Directive::Instruction { mnemonic, operands } => instruction_size(mnemonic, operands),
+                Directive::Bytes(operands) => operands.iter().map(|op| string_literal(op).map_or(1, str::len)).sum(),
+                Directive::Words(operands) => operands.len() * 2,
+                Directive::Binary(data) => data.len(),
+            };
+
+            self.origin.get_or_insert(self.addr);
+            self.statements.push(Statement { location, addr: self.addr, directive });
+            self.addr += size;
+        }
+
+        Ok(())
+    }
+
+    // Second pass: every label is known, so operands can be evaluated and encoded
+    fn emit(&self) -> Result<Vec<u8>, Chip8Error> {
+        let origin = self.origin.unwrap_or(self.addr);
+        let mut rom = Vec::new();
+
+        for statement in &self.statements {
+            let location = &statement.location;
+            let offset = statement.addr - origin;
+            if rom.len() < offset {
+                rom.resize(offset, 0);
+            }
+
+            match &statement.directive {
+                Directive::Instruction { mnemonic, operands } => {
+                    let (instruction, operand) = self.instruction(location, mnemonic, operands)?;
+                    rom.extend_from_slice(&instruction.encode().to_be_bytes());
+                    if let Some(operand) = operand {
+                        rom.extend_from_slice(&operand.to_be_bytes());
+                    }
+                }
+                Directive::Bytes(operands) => {
+                    for operand in operands {
+                        match string_literal(operand) {
+                            Some(text) => rom.extend_from_slice(text.as_bytes()),
+                            None => rom.push(self.byte(location, operand)?),
+                        }
+                    }
+                }
+                Directive::Words(operands) => {
+                    for operand in operands {
+                        let word = self.ranged(location, operand, -0x8000, 0xFFFF)? as u16;
+                        rom.extend_from_slice(&word.to_be_bytes());
+                    }
+                }
+                Directive::Binary(data) => rom.extend_from_slice(data),
+            }
+        }
+
+        if origin + rom.len() > 0x10000 {
+            return Err(Chip8Error::RomTooLarge { size: rom.len(), max: 0x10000 - origin });
+        }
+        Ok(rom)
+    }
+
+    fn instruction(
+        &self,
+        location: &Location,
+        mnemonic: &str,
+        operands: &[String],
+    ) -> Result<(Instruction, Option<u16>), Chip8Error> {
+        let vx = |op: &str| register(op).ok_or_else(|| location.error(format!("expected a register, got '{}'", op)));
+        let addr = |op: &str| self.ranged(location, op, 0, 0xFFF).map(|a| a as u16);
+        let nibble = |op: &str| self.ranged(location, op, 0, 0xF).map(|n| n as u8);
+        let byte = |op: &str| self.byte(location, op);
+
+        let operands: Vec<&str> = operands.iter().map(String::as_str).collect();
+        let instruction = match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => Instruction::Cls,
+            ("RET", []) => Instruction::Ret,
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::Low,
+            ("HIGH", []) => Instruction::High,
+            ("AUDIO", []) => Instruction::Audio,
+            ("SYS", [a]) => Instruction::Sys(addr(a)?),
+            ("JP", [v0, a]) if register(v0) == Some(0) => Instruction::JpV0(addr(a)?),
+            ("JP", [a]) => Instruction::Jp(addr(a)?),
+            ("CALL", [a]) => Instruction::Call(addr(a)?),
+            ("SCD", [n]) => Instruction::ScrollDown { n: nibble(n)? },
+            ("SCU", [n]) => Instruction::ScrollUp { n: nibble(n)? },
+            ("PLANE", [n]) => Instruction::Plane(nibble(n)?),
+            ("SE", [x, y]) => match register(y) {
+                Some(y) => Instruction::SeVxVy { x: vx(x)?, y },
+                None => Instruction::SeVxByte { x: vx(x)?, nn: byte(y)? },
+            },
+            ("SNE", [x, y]) => match register(y) {
+                Some(y) => Instruction::SneVxVy { x: vx(x)?, y },
+                None => Instruction::SneVxByte { x: vx(x)?, nn: byte(y)? },
+            },
+            ("SAVE", [x, y]) => Instruction::SaveVxVy { x: vx(x)?, y: vx(y)? },
+            ("LOAD", [x, y]) => Instruction::LoadVxVy { x: vx(x)?, y: vx(y)? },
+            ("ADD", [i, x]) if i.eq_ignore_ascii_case("I") => Instruction::AddIVx { x: vx(x)? },
+            ("ADD", [x, y]) => match register(y) {
+                Some(y) => Instruction::AddVxVy { x: vx(x)?, y },
+                None => Instruction::AddVxByte { x: vx(x)?, nn: byte(y)? },
+            },
+            ("OR", [x, y]) => Instruction::OrVxVy { x: vx(x)?, y: vx(y)? },
+            ("AND", [x, y]) => Instruction::AndVxVy { x: vx(x)?, y: vx(y)? },
+            ("XOR", [x, y]) => Instruction::XorVxVy { x: vx(x)?, y: vx(y)? },
+            ("SUB", [x, y]) => Instruction::SubVxVy { x: vx(x)?, y: vx(y)? },
+            ("SUBN", [x, y]) => Instruction::SubnVxVy { x: vx(x)?, y: vx(y)? },
+            ("SHR", [x]) => Instruction::ShrVxVy { x: vx(x)?, y: vx(x)? },
+            ("SHR", [x, y]) => Instruction::ShrVxVy { x: vx(x)?, y: vx(y)? },
+            ("SHL", [x]) => Instruction::ShlVxVy { x: vx(x)?, y: vx(x)? },
+            ("SHL", [x, y]) => Instruction::ShlVxVy { x: vx(x)?, y: vx(y)? },
+            ("RND", [x, nn]) => Instruction::RndVxByte { x: vx(x)?, nn: byte(nn)? },
+            ("DRW", [x, y, n]) => Instruction::Drw { x: vx(x)?, y: vx(y)?, n: nibble(n)? },
+            ("SKP", [x]) => Instruction::SkpVx { x: vx(x)? },
+            ("SKNP", [x]) => Instruction::SknpVx { x: vx(x)? },
+            ("PITCH", [x]) => Instruction::PitchVx { x: vx(x)? },
+            ("LD", [dest, src]) => {
+                if let Some(long) = long_operand(dest, src) {
+                    let operand = self.ranged(location, long, 0, 0xFFFF)? as u16;
+                    return Ok((Instruction::LdILong, Some(operand)));
+                }
+                match (dest.to_ascii_uppercase().as_str(), src.to_ascii_uppercase().as_str()) {
+                    ("I", _) => Instruction::LdI(addr(src)?),
+                    ("DT", _) => Instruction::LdDtVx { x: vx(src)? },
+                    ("ST", _) => Instruction::LdStVx { x: vx(src)? },
+                    ("F", _) => Instruction::LdFVx { x: vx(src)? },
+                    ("HF", _) => Instruction::LdHfVx { x: vx(src)? },
+                    ("B", _) => Instruction::LdBVx { x: vx(src)? },
+                    ("[I]", _) => Instruction::LdIVx { x: vx(src)? },
+                    ("R", _) => Instruction::LdRVx { x: vx(src)? },
+                    (_, "DT") => Instruction::LdVxDt { x: vx(dest)? },
+                    (_, "K") => Instruction::LdVxK { x: vx(dest)? },
+                    (_, "[I]") => Instruction::LdVxI { x: vx(dest)? },
+                    (_, "R") => Instruction::LdVxR { x: vx(dest)? },
+                    _ => match register(src) {
+                        Some(y) => Instruction::LdVxVy { x: vx(dest)?, y },
+                        None => Instruction::LdVxByte { x: vx(dest)?, nn: byte(src)? },
+                    },
+                }
+            }
+            _ => {
+                return Err(location.error(format!(
+                    "unknown instruction '{} {}'",
+                    mnemonic,
+                    operands.join(", ")
+                )))
+            }
+        };
+
+        Ok((instruction, None))
+    }
+
+    fn define(&mut self, location: &Location, name: &str, value: i64) -> Result<(), Chip8Error> {
+        if !is_identifier(name) {
+            return Err(location.error(format!("'{}' is not a valid name", name)));
+        }
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(location.error(format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn byte(&self, location: &Location, expr: &str) -> Result<u8, Chip8Error> {
+        // Negative bytes are stored in two's complement, so `ADD V0, -1` decrements
+        self.ranged(location, expr, -0x80, 0xFF).map(|value| value as u8)
+    }
+
+    fn ranged(&self, location: &Location, expr: &str, min: i64, max: i64) -> Result<i64, Chip8Error> {
+        let value = self.eval(location, expr)?;
+        if value < min || value > max {
+            return Err(location.error(format!("'{}' is {} which does not fit in {:#X}", expr, value, max)));
+        }
+        Ok(value)
+    }
+
+    // Evaluates terms joined by + and -
+    fn eval(&self, location: &Location, expr: &str) -> Result<i64, Chip8Error> {
+        let mut total = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+
+        for c in expr.chars().chain(std::iter::once('+')) {
+            if (c == '+' || c == '-') && !term.trim().is_empty() {
+                total += sign * self.term(location, term.trim())?;
+                term.clear();
+                sign = if c == '-' { -1 } else { 1 };
+            } else if c == '-' {
+                sign = -sign;
+            } else if c != '+' {
+                term.push(c);
+            }
+        }
+
+        if !term.trim().is_empty() || expr.trim().is_empty() {
+            return Err(location.error(format!("cannot evaluate '{}'", expr)));
+        }
+        Ok(total)
+    }
+
+    fn term(&self, location: &Location, term: &str) -> Result<i64, Chip8Error> {
+        if let Some(value) = parse_number(term) {
+            return Ok(value);
+        }
+        self.symbols
+            .get(term)
+            .copied()
+            .ok_or_else(|| location.error(format!("'{}' is not defined", term)))
+    }
+}
+
+fn instruction_size(mnemonic: &str, operands: &[String]) -> usize {
+    match operands {
+        [dest, src] if mnemonic == "LD" && long_operand(dest, src).is_some() => 4,
+        _ => 2,
+    }
+}
+
+// The address in `LD I, LONG addr`
+fn long_operand<'a>(dest: &str, src: &'a str) -> Option<&'a str> {
+    if !dest.eq_ignore_ascii_case("I") {
+        return None;
+    }
+    let (keyword, operand) = split_word(src);
+    if keyword.eq_ignore_ascii_case("LONG") {
+        Some(operand)
+    } else {
+        None
+    }
+}
+
+fn include_path(location: &Location, dir: &Path, operands: &[String]) -> Result<PathBuf, Chip8Error> {
+    match operands {
+        [path] => match string_literal(path) {
+            Some(path) => Ok(dir.join(path)),
+            None => Err(location.error("expected a quoted file name")),
+        },
+        _ => Err(location.error("expected a quoted file name")),
+    }
+}
+
+fn register(operand: &str) -> Option<u8> {
+    let mut chars = operand.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V' | 'v'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+        _ => None,
+    }
+}
+
+fn parse_number(term: &str) -> Option<i64> {
+    let lower = term.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix('#').or(lower.strip_prefix('$')).or(lower.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix('%').or(lower.strip_prefix("0b")) {
+        i64::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && register(name).is_none()
+}
+
+fn string_literal(operand: &str) -> Option<&str> {
+    operand.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// `name:` at the start of a line, optionally followed by a statement
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let (first, _) = split_word(text);
+    let label = first.strip_suffix(':')?;
+    Some((label, &text[first.len()..]))
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    }
+}
+
+fn split_operands(text: &str) -> Vec<String> {
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            ',' if !quoted => operands.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() || !operands.is_empty() {
+        operands.push(current.trim().to_string());
+    }
+
+    operands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn assemble_should_encode_instructions() {
+        let rom = assemble(
+            "
+            CLS
+            LD V0, #0A      ; hex immediate
+            ADD V0, V1
+            LD I, 0x300
+            DRW V0, V1, 5
+            LD [I], V3
+            SHR V2
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(rom, vec![0x00, 0xE0, 0x60, 0x0A, 0x80, 0x14, 0xA3, 0x00, 0xD0, 0x15, 0xF3, 0x55, 0x82, 0x26]);
+    }
+
+    #[test]
+    fn assemble_should_resolve_labels_and_constants() {
+        let rom = assemble(
+            "
+            SPEED EQU 3
+            start:
+                CALL draw
+                JP start
+            draw: ADD V0, SPEED + 1
+                LD I, sprite
+                RET
+            sprite:
+                DB %11110000, $90, \"A\"
+                DW sprite - start
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rom,
+            vec![0x22, 0x04, 0x12, 0x00, 0x70, 0x04, 0xA2, 0x0A, 0x00, 0xEE, 0xF0, 0x90, 0x41, 0x00, 0x0A]
+        );
+    }
+
+    #[test]
+    fn assemble_should_encode_the_long_load() {
+        let rom = assemble("LD I, LONG data\nORG #210\ndata: DB 1").unwrap();
+
+        assert_eq!(rom.len(), 0x11);
+        assert_eq!(&rom[..4], &[0xF0, 0x00, 0x02, 0x10]);
+        assert_eq!(rom[0x10], 1);
+    }
+
+    #[test]
+    fn assemble_should_report_the_failing_line() {
+        let err = assemble("CLS\nLD V0, 256").unwrap_err();
+        assert!(matches!(err, Chip8Error::Asm { line: 2, .. }), "{}", err);
+
+        let err = assemble("JP nowhere").unwrap_err();
+        assert_eq!(err.to_string(), "<input>:1: 'nowhere' is not defined");
+
+        assert!(assemble("FOO V0").is_err());
+        assert!(assemble("a: CLS\na: CLS").is_err());
+    }
+
+    #[test]
+    fn assemble_should_round_trip_the_disassembler() {
+        let roms: [&[u8]; 4] = [
+            include_bytes!("../../roms/BLINKY"),
+            include_bytes!("../../roms/MERLIN"),
+            include_bytes!("../../roms/VBRIX"),
+            include_bytes!("../../roms/TETRIS"),
+        ];
+
+        for rom in roms {
+            let listing = disassemble(rom, 0x200).to_string();
+            assert_eq!(assemble(&listing).unwrap(), rom);
+        }
+
+        let listing = disassemble(&[0xF0, 0x00, 0x06, 0x04, 0xAB], 0x600).to_string();
+        assert_eq!(assemble(&listing).unwrap(), vec![0xF0, 0x00, 0x06, 0x04, 0xAB]);
+    }
+}
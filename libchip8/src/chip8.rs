@@ -1,273 +1,930 @@
+use crate::error::Chip8Error;
 use crate::fonts;
+use crate::instruction::Instruction;
+use crate::observer::Observer;
+use crate::platform::{Config, Platform, StackPolicy, FRAME_HZ};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+use crate::state;
+use crate::trace::{TraceEntry, TraceSink};
 use std::fs;
+use std::io::Read;
+use std::ops::Range;
+use std::path::Path;
 
-const STARTING_PC_OFFSET: u16 = 0x200;
+const BIG_FONTS_OFFSET: usize = fonts::FONTS.len();
 pub const GFX_WIDTH: usize = 64;
 pub const GFX_HEIGHT: usize = 32;
+pub const HIRES_GFX_WIDTH: usize = 128;
+pub const HIRES_GFX_HEIGHT: usize = 64;
+pub const MEMORY_SIZE: usize = 0x1000;
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum InstructionSet {
+    /// The original CHIP-8 instructions and 64x32 display.
+    Chip8,
+    /// CHIP-8 plus the 128x64 hires display, 16x16 sprites, big font and RPL flags.
+    SuperChip10,
+    /// SUPER-CHIP 1.0 plus scrolling.
+    #[default]
+    SuperChip11,
+    /// SUPER-CHIP 1.1 plus the XO-CHIP extensions: 64 KB of memory, two bitplanes and an audio pattern buffer.
+    XoChip,
+}
 
-pub struct Chip8 {
-    memory: [u8; 4096],
-    v: [u8; 16],
-    stack: [u16; 16],
-    pub input: [u8; 16],
-    pub gfx: [u8; GFX_WIDTH * GFX_HEIGHT],
+impl InstructionSet {
+    pub fn has_super_chip(self) -> bool {
+        self != InstructionSet::Chip8
+    }
 
-    i: u16,
-    pc: u16,
-    sp: u16,
+    pub fn has_scrolling(self) -> bool {
+        matches!(self, InstructionSet::SuperChip11 | InstructionSet::XoChip)
+    }
+
+    pub fn has_xo_chip(self) -> bool {
+        self == InstructionSet::XoChip
+    }
+}
 
-    delay_timer: u8,
-    sound_timer: u8,
+pub struct Chip8 {
+    pub(crate) instruction_set: InstructionSet,
+    pub(crate) quirks: Quirks,
+    pub(crate) memory: Vec<u8>,
+    pub(crate) v: [u8; 16],
+    pub(crate) stack: Vec<u16>,
+    pub(crate) stack_policy: StackPolicy,
+    pub(crate) font_address: u16,
+    pub(crate) load_address: u16,
+    pub(crate) timer_hz: u32,
+    // Timer ticks owed, in 1/FRAME_HZ steps, so rates that don't divide evenly keep their average
+    pub(crate) timer_phase: u32,
+    pub(crate) rpl: [u8; 16],
+    pub input: [u8; 16],
+    /// One byte per pixel of the active resolution, row major. Each bit is a bitplane,
+    /// so outside of XO-CHIP pixels are 0 or 1 and with XO-CHIP they form a 4 color index.
+    pub gfx: Vec<u8>,
+    pub(crate) audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub(crate) rng: Rng,
+    pub(crate) seed: u64,
+    pub(crate) rom_hash: u64,
+
+    pub(crate) i: u16,
+    pub(crate) pc: u16,
+    pub(crate) sp: u16,
+
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+
+    pub(crate) pitch: u8,
+    pub(crate) plane_mask: u8,
+
+    pub(crate) hires: bool,
+    pub(crate) halted: bool,
+    pub(crate) draw_flag: bool,
+    pub(crate) vblank: bool,
+
+    trace_sink: Option<Box<dyn TraceSink>>,
+    observer: Option<Box<dyn Observer>>,
+    pub(crate) halt_reason: Option<Chip8Error>,
+}
 
-    draw_flag: bool,
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Self::with_instruction_set(InstructionSet::default())
+    }
+
+    pub fn with_instruction_set(instruction_set: InstructionSet) -> Chip8 {
+        Self::with_quirks(instruction_set, Quirks::default())
+    }
+
+    pub fn with_quirks(instruction_set: InstructionSet, quirks: Quirks) -> Chip8 {
+        let memory_size = if instruction_set.has_xo_chip() { XO_MEMORY_SIZE } else { MEMORY_SIZE };
+
+        Self::with_config(Config {
+            instruction_set,
+            quirks,
+            memory_size,
+            ..Config::default()
+        })
+    }
+
+    pub fn with_platform(platform: Platform) -> Chip8 {
+        Self::with_config(platform.config())
+    }
+
+    /// Builds a machine from `config`, or fails when `Config::validate` rejects it.
+    pub fn try_with_config(config: Config) -> Result<Chip8, Chip8Error> {
+        config.validate()?;
+        Ok(Self::with_config(config))
+    }
+
+    /// Builds a machine from `config`.
+    ///
+    /// # Panics
+    ///
+    /// When `Config::validate` rejects the config. Use `try_with_config` for configs that come
+    /// from the user.
+    pub fn with_config(config: Config) -> Chip8 {
+        if let Err(e) = config.validate() {
+            panic!("{}", e);
+        }
+        let rng = Rng::from_entropy();
+
         let mut c8 = Chip8 {
-            memory: [0; 4096],
+            instruction_set: config.instruction_set,
+            quirks: config.quirks,
+            memory: vec![0; config.memory_size],
             v: [0; 16],
-            stack: [0; 16],
+            stack: vec![0; config.stack_depth],
+            stack_policy: config.stack_policy,
+            font_address: config.font_address,
+            load_address: config.load_address,
+            timer_hz: config.timer_hz,
+            timer_phase: 0,
+            rpl: [0; 16],
             input: [0; 16],
-            gfx: [0; GFX_WIDTH * GFX_HEIGHT],
+            gfx: vec![0; GFX_WIDTH * GFX_HEIGHT],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            rng,
+            seed: rng.state(),
+            rom_hash: 0,
 
             i: 0,
-            pc: STARTING_PC_OFFSET,
+            pc: config.start_address,
             sp: 0,
 
             delay_timer: 0,
             sound_timer: 0,
 
+            pitch: DEFAULT_PITCH,
+            plane_mask: 1,
+
+            hires: false,
+            halted: false,
             draw_flag: false,
+            vblank: true,
+
+            trace_sink: None,
+            observer: None,
+            halt_reason: None,
         };
 
-        for i in 0..fonts::FONTS.len() {
-            c8.memory[i] = fonts::FONTS[i];
+        let small = c8.font_address as usize;
+        c8.memory[small..small + BIG_FONTS_OFFSET].copy_from_slice(&config.font.small);
+        if let Some(big) = config.font.big {
+            c8.memory[small + BIG_FONTS_OFFSET..small + BIG_FONTS_OFFSET + big.len()].copy_from_slice(&big);
         }
 
-        return c8;
+        c8
     }
 
     pub fn is_draw_ready(&self) -> bool {
         self.draw_flag
     }
 
-    pub fn tick(&mut self) {
+    /// True once the rom has executed 00FD (exit). A halted machine ignores further instructions.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// The stack fault a machine under `StackPolicy::Halt` halted on. None while it runs and
+    /// after a 00FD exit.
+    pub fn halt_reason(&self) -> Option<&Chip8Error> {
+        self.halt_reason.as_ref()
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn instruction_set(&self) -> InstructionSet {
+        self.instruction_set
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    /// The whole address space, fonts and rom included.
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The return addresses of the active calls, outermost first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    /// How many nested calls the stack holds.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn stack_policy(&self) -> StackPolicy {
+        self.stack_policy
+    }
+
+    /// Where the 4x5 font starts, the 8x10 font follows it.
+    pub fn font_address(&self) -> u16 {
+        self.font_address
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// True while the sound timer is running, which is when the buzzer should sound.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn set_register(&mut self, x: usize, value: u8) -> Result<(), Chip8Error> {
+        let register = self.v.get_mut(x).ok_or(Chip8Error::InvalidRegister { x })?;
+        *register = value;
+        Ok(())
+    }
+
+    /// Moves execution to `addr`, which has to hold a whole instruction.
+    pub fn set_pc(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        self.memory_range(addr as usize, 2)?;
+        self.pc = addr;
+        Ok(())
+    }
+
+    pub fn set_i(&mut self, addr: u16) {
+        self.i = addr;
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+    }
+
+    /// Replaces the active calls with `stack`, outermost first.
+    pub fn set_stack(&mut self, stack: &[u16]) -> Result<(), Chip8Error> {
+        if stack.len() > self.stack.len() {
+            return Err(Chip8Error::StackOverflow { pc: self.pc });
+        }
+        self.stack[..stack.len()].copy_from_slice(stack);
+        self.sp = stack.len() as u16;
+        Ok(())
+    }
+
+    /// Copies `bytes` into memory at `addr`, failing without writing anything if they don't fit.
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let range = self.memory_range(addr as usize, bytes.len())?;
+        self.memory[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Restarts the random number sequence used by CXNN, so runs with the same seed and
+    /// inputs behave identically.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    /// FNV-1a hash of the loaded rom, recorded in save states. 0 until a rom is loaded.
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    /// The seed the random number sequence started from, either random or given to `set_seed`.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The 128 bit sample pattern loaded by XO-CHIP F002, played back one bit per sample.
+    pub fn audio_pattern(&self) -> &[u8; AUDIO_PATTERN_SIZE] {
+        &self.audio_pattern
+    }
+
+    /// Sample rate in Hz at which the audio pattern is played, as set by XO-CHIP FX3A.
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    /// Width in pixels of the active resolution, 64 in lores and 128 in hires.
+    pub fn display_width(&self) -> usize {
+        if self.hires {
+            HIRES_GFX_WIDTH
+        } else {
+            GFX_WIDTH
+        }
+    }
+
+    /// Height in pixels of the active resolution, 32 in lores and 64 in hires.
+    pub fn display_height(&self) -> usize {
+        if self.hires {
+            HIRES_GFX_HEIGHT
+        } else {
+            GFX_HEIGHT
+        }
+    }
+
+    /// Runs one 60 Hz frame: `cycles_per_frame` instructions followed by `tick_timers`.
+    /// `is_draw_ready` afterwards reports whether the display changed during the frame.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), Chip8Error> {
         self.draw_flag = false;
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1
+        for _ in 0..cycles_per_frame {
+            if self.halted {
+                break;
+            }
+            self.step_instruction()?;
+        }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Executes one instruction and ticks the timers once, so the timers run at the instruction
+    /// rate as they did before `run_frame` existed.
+    #[deprecated(note = "use run_frame, or step_instruction and tick_timers at their own rates")]
+    pub fn tick(&mut self) -> Result<(), Chip8Error> {
+        self.run_frame(1)
+    }
+
+    /// Fetches and executes the instruction at pc, without touching the timers.
+    pub fn step_instruction(&mut self) -> Result<(), Chip8Error> {
+        if self.halted {
+            return Ok(());
+        }
+
+        let range = self.memory_range(self.pc as usize, 2)?;
+        let op_code = (self.memory[range.start] as u16) << 8 | (self.memory[range.start + 1] as u16);
+
+        // Traced before executing, so an instruction that faults is still the last line
+        if self.trace_sink.is_some() {
+            let entry = TraceEntry::capture(self, self.pc, op_code);
+            if let Some(sink) = self.trace_sink.as_mut() {
+                sink.trace(&entry)?;
+            }
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1
+        self.exec_op(op_code)
+    }
+
+    /// Sends every instruction to `sink` before it executes, or stops tracing when None.
+    pub fn set_trace_sink(&mut self, sink: Option<Box<dyn TraceSink>>) {
+        self.trace_sink = sink;
+    }
+
+    /// Reports executed instructions and their memory accesses to `observer`, or stops when None.
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.observer = observer;
+    }
+
+    pub(crate) fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take()
+    }
+
+    /// Decodes the instruction stored at `addr`, None when the word there is not an opcode or lies outside memory.
+    pub fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        let word = self.memory.get(addr as usize..addr as usize + 2)?;
+        Instruction::decode((word[0] as u16) << 8 | word[1] as u16)
+    }
+
+    /// Advances the delay and sound timers by one 60 Hz frame, which decrements them once at the
+    /// default timer rate and proportionally more or less often at others. Call this once per
+    /// frame, independently of the instruction rate.
+    pub fn tick_timers(&mut self) {
+        self.timer_phase += self.timer_hz;
+        while self.timer_phase >= FRAME_HZ {
+            self.timer_phase -= FRAME_HZ;
+            self.delay_timer = self.delay_timer.saturating_sub(1);
+            self.sound_timer = self.sound_timer.saturating_sub(1);
         }
+        self.vblank = true;
+    }
 
-        let op_code = (self.memory[self.pc as usize] as u16) << 8
-            | (self.memory[self.pc as usize + 1] as u16);
-        self.exec_op(op_code);
+    /// How many times a second the delay and sound timers count down.
+    pub fn timer_hz(&self) -> u32 {
+        self.timer_hz
     }
 
     pub fn debug_gfx_to_stdout(&self) {
-        for col in 0..GFX_HEIGHT {
-            for row in 0..GFX_WIDTH {
-                if self.gfx[col * GFX_WIDTH + row] == 0 {
+        let width = self.display_width();
+        for col in 0..self.display_height() {
+            for row in 0..width {
+                if self.gfx[col * width + row] == 0 {
                     print!("-")
                 } else {
                     print!("X")
                 }
             }
 
-            print!("\n");
+            println!();
         }
     }
 
-    pub fn load(&mut self, file_path: &str) {
-        let data = fs::read(file_path).unwrap();
-        for (i, it) in data.iter().enumerate() {
-            self.memory[self.pc as usize + i] = *it;
-        }
+    pub fn load<P: AsRef<Path>>(&mut self, file_path: P) -> Result<(), Chip8Error> {
+        self.load_bytes(&fs::read(file_path)?)
+    }
+
+    /// Reads a rom until the end of `reader`, from stdin or an archive entry for example.
+    pub fn load_from_reader<R: Read>(&mut self, mut reader: R) -> Result<(), Chip8Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        self.load_bytes(&data)
     }
 
-    fn exec_op(&mut self, opcode: u16) {
-        let codes = (
-            (opcode & 0xF000) >> 12 as u8,
-            (opcode & 0x0F00) >> 8 as u8,
-            (opcode & 0x00F0) >> 4 as u8,
-            (opcode & 0x000F) as u8,
-        );
+    /// Copies a rom to the load address. Memory is left untouched if it doesn't fit.
+    pub fn load_bytes(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let start = self.load_address as usize;
+        let max = self.memory.len().saturating_sub(start);
+        if data.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: data.len(), max });
+        }
+
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        self.rom_hash = state::rom_hash(data);
+        Ok(())
+    }
 
-        let nnn = opcode & 0x0FFF;
-        let nn = (opcode & 0x00FF) as u8;
+    /// Where roms are loaded.
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
 
-        let x = codes.1 as usize;
-        let y = codes.2 as usize;
-        let n = codes.3 as usize;
+    fn exec_op(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let pc = self.pc;
+        let unknown = move || Chip8Error::UnknownOpcode { pc, opcode };
+        let instruction = Instruction::decode(opcode).ok_or_else(unknown)?;
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_execute(pc, instruction);
+        }
 
         let mut pc_step: u16 = 2;
 
-        match codes {
-            (0x0, 0x0, 0xE, 0x0) => self.gfx.fill(0),
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.sp = self.sp.wrapping_sub(1);
+        match instruction {
+            Instruction::ScrollDown { n } if self.instruction_set.has_scrolling() => {
+                self.scroll_down(n as usize);
+                self.draw_flag = true;
+            }
+            Instruction::ScrollUp { n } if self.is_xo_chip() => {
+                self.scroll_up(n as usize);
+                self.draw_flag = true;
+            }
+            Instruction::Cls => {
+                for pixel in self.gfx.iter_mut() {
+                    *pixel &= !self.plane_mask;
+                }
+                self.draw_flag = true;
+            }
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    if !self.stack_fault(Chip8Error::StackUnderflow { pc: self.pc })? {
+                        return Ok(());
+                    }
+                    self.sp = self.stack.len() as u16;
+                }
+                self.sp -= 1;
                 self.pc = self.stack[self.sp as usize];
-                return;
+                return Ok(());
+            }
+            Instruction::ScrollRight if self.instruction_set.has_scrolling() => {
+                self.scroll_right(4);
+                self.draw_flag = true;
+            }
+            Instruction::ScrollLeft if self.instruction_set.has_scrolling() => {
+                self.scroll_left(4);
+                self.draw_flag = true;
+            }
+            Instruction::Exit if self.is_super_chip() => {
+                self.halted = true;
+                return Ok(());
             }
-            (0x1, _, _, _) => {
-                self.pc = nnn;
-                return;
+            Instruction::Low if self.is_super_chip() => self.set_hires(false),
+            Instruction::High if self.is_super_chip() => self.set_hires(true),
+            Instruction::Jp(addr) => {
+                self.pc = addr;
+                return Ok(());
             }
-            (0x2, _, _, _) => {
-                self.stack[self.sp as usize] = self.pc;
-                self.sp = self.sp.wrapping_add(1);
-                self.pc = nnn;
-                return;
+            Instruction::Call(addr) => {
+                if self.sp as usize >= self.stack.len() {
+                    if !self.stack_fault(Chip8Error::StackOverflow { pc: self.pc })? {
+                        return Ok(());
+                    }
+                    self.sp = 0;
+                }
+                // Push the return address so 00EE resumes after the call
+                self.stack[self.sp as usize] = self.next_pc(2)?;
+                self.sp += 1;
+                self.pc = addr;
+                return Ok(());
             }
-            (0x3, _, _, _) => {
-                if self.v[x] == nn {
+            Instruction::SeVxByte { x, nn } => {
+                if self.v[x as usize] == nn {
                     pc_step = self.skip_next();
                 }
             }
-            (0x4, _, _, _) => {
-                if self.v[x] != nn {
+            Instruction::SneVxByte { x, nn } => {
+                if self.v[x as usize] != nn {
                     pc_step = self.skip_next();
                 }
             }
-            (0x5, _, _, 0x0) => {
-                if self.v[x] == self.v[y] {
+            Instruction::SeVxVy { x, y } => {
+                if self.v[x as usize] == self.v[y as usize] {
                     pc_step = self.skip_next();
                 }
             }
-            (0x6, _, _, _) => self.v[x] = nn,
-            (0x7, _, _, _) => self.v[x] = self.v[x].wrapping_add(nn),
-            (0x8, _, _, 0x0) => self.v[x] = self.v[y],
-            (0x8, _, _, 0x1) => self.v[x] |= self.v[y],
-            (0x8, _, _, 0x2) => self.v[x] &= self.v[y],
-            (0x8, _, _, 0x3) => self.v[x] ^= self.v[y],
-            (0x8, _, _, 0x4) => {
-                if self.v[y] > (0xFF - self.v[x]) {
-                    self.v[0xF] = 1;
-                } else {
-                    self.v[0xF] = 0;
+            Instruction::SaveVxVy { x, y } if self.is_xo_chip() => {
+                let registers = Self::register_range(x as usize, y as usize);
+                let dest = self.memory_range(self.i as usize, registers.len())?;
+                for (addr, register) in dest.clone().zip(registers) {
+                    self.memory[addr] = self.v[register];
                 }
-                self.v[x] = self.v[x].wrapping_add(self.v[y]);
+                self.observe_writes(dest);
             }
-            (0x8, _, _, 0x5) => {
-                if self.v[y] > self.v[x] {
-                    self.v[0xF] = 0;
-                } else {
-                    self.v[0xF] = 1;
+            Instruction::LoadVxVy { x, y } if self.is_xo_chip() => {
+                let registers = Self::register_range(x as usize, y as usize);
+                let src = self.memory_range(self.i as usize, registers.len())?;
+                self.observe_reads(src.clone());
+                for (addr, register) in src.zip(registers) {
+                    self.v[register] = self.memory[addr];
                 }
-                self.v[x] = self.v[x].wrapping_sub(self.v[y]);
             }
-            (0x8, _, _, 0x6) => {
-                self.v[0xF] = self.v[x] & 1;
-                self.v[x] >>= 1;
+            Instruction::LdVxByte { x, nn } => self.v[x as usize] = nn,
+            Instruction::AddVxByte { x, nn } => self.v[x as usize] = self.v[x as usize].wrapping_add(nn),
+            Instruction::LdVxVy { x, y } => self.v[x as usize] = self.v[y as usize],
+            Instruction::OrVxVy { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                self.reset_vf_after_logic();
             }
-            (0x8, _, _, 0x7) => {
-                if self.v[x] > self.v[y] {
-                    self.v[0xF] = 0;
-                } else {
-                    self.v[0xF] = 1;
-                }
-                self.v[x] = self.v[y].wrapping_sub(self.v[x]);
+            Instruction::AndVxVy { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                self.reset_vf_after_logic();
             }
-            (0x8, _, _, 0xE) => {
-                self.v[0xF] = (self.v[x] >> 7) & 1;
-                self.v[x] <<= 1;
+            Instruction::XorVxVy { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                self.reset_vf_after_logic();
             }
-
-            (0x9, _, _, 0x0) => {
-                if self.v[x] != self.v[y] {
+            Instruction::AddVxVy { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (value, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = value;
+                self.v[0xF] = carry as u8;
+            }
+            Instruction::SubVxVy { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (value, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                self.v[x] = value;
+                self.v[0xF] = !borrow as u8;
+            }
+            Instruction::ShrVxVy { x, y } => {
+                let value = self.shift_source(x as usize, y as usize);
+                self.v[x as usize] = value >> 1;
+                self.v[0xF] = value & 1;
+            }
+            Instruction::SubnVxVy { x, y } => {
+                let (x, y) = (x as usize, y as usize);
+                let (value, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                self.v[x] = value;
+                self.v[0xF] = !borrow as u8;
+            }
+            Instruction::ShlVxVy { x, y } => {
+                let value = self.shift_source(x as usize, y as usize);
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = (value >> 7) & 1;
+            }
+            Instruction::SneVxVy { x, y } => {
+                if self.v[x as usize] != self.v[y as usize] {
                     pc_step = self.skip_next();
                 }
             }
-            (0xA, _, _, _) => self.i = nnn,
-            (0xB, _, _, _) => {
-                self.pc = (self.v[0] as u16 + nnn) as u16;
-                return; // Jump to address by not letting pc_step increment self.pc
+            Instruction::LdI(addr) => self.i = addr,
+            Instruction::JpV0(addr) => {
+                let register = if self.quirks.jump_uses_vx { (addr >> 8) as usize } else { 0 };
+                self.pc = self.v[register] as u16 + addr;
+                return Ok(()); // Jump to address by not letting pc_step increment self.pc
             }
-            (0xC, _, _, _) => self.v[x] = rand::random::<u8>() & nn,
-            (0xD, _, _, _) => {
-                self.draw_flag = true;
-                self.v[0xF] = 0;
-
-                for y_line in 0..n {
-                    let px = self.memory[self.i as usize + y_line];
-
-                    for x_line in 0u8..8 {
-                        if (px & (0x80 >> x_line)) != 0 {
-                            // if drawing causes any pixel to be erased set the
-                            // collision flag to 1
-                            if self.gfx[(self.v[x] as usize + x_line as usize + ((self.v[y] as usize + y_line) * 64))] == 1 {
-                                self.v[0xF] = 1;
-                            }
-
-                            // set pixel value by using XOR
-                            self.gfx[self.v[x] as usize + x_line as usize + ((self.v[y] as usize + y_line) * 64)] ^= 1;
-                        }
-                    }
-                }
+            Instruction::RndVxByte { x, nn } => self.v[x as usize] = self.rng.next_u8() & nn,
+            Instruction::Drw { .. } if self.quirks.display_wait && !self.vblank => {
+                return Ok(()); // Wait for the next vertical blank by not advancing self.pc
+            }
+            Instruction::Drw { x, y, n: 0 } if self.is_super_chip() => {
+                self.draw_sprite(self.v[x as usize], self.v[y as usize], 16, 2)?
             }
-            (0xE, _, 0x9, 0xE) => {
-                if self.input[self.v[x] as usize] == 1 {
+            Instruction::Drw { x, y, n } => self.draw_sprite(self.v[x as usize], self.v[y as usize], n as usize, 1)?,
+            Instruction::SkpVx { x } => {
+                if self.is_key_pressed(self.v[x as usize])? {
                     pc_step = self.skip_next();
                 }
             }
-            (0xE, _, 0xA, 0x1) => {
-                if self.input[self.v[x] as usize] != 1 {
+            Instruction::SknpVx { x } => {
+                if !self.is_key_pressed(self.v[x as usize])? {
                     pc_step = self.skip_next();
                 }
             }
-            (0xF, _, 0x0, 0x7) => self.v[x] = self.delay_timer,
-            (0xF, _, 0x0, 0xA) => {
-                let mut input_pressed = false;
-                for i in 0..self.input.len() {
-                    if self.input[i] == 1 {
-                        self.v[x] = i as u8;
-                        input_pressed = true;
-                        break;
+            Instruction::LdILong if self.is_xo_chip() => {
+                let operand = self.memory_range(self.pc as usize + 2, 2)?;
+                self.i = (self.memory[operand.start] as u16) << 8 | self.memory[operand.start + 1] as u16;
+                pc_step = instruction.size();
+            }
+            Instruction::Plane(mask) if self.is_xo_chip() => self.plane_mask = mask & 0b11,
+            Instruction::Audio if self.is_xo_chip() => {
+                let src = self.memory_range(self.i as usize, AUDIO_PATTERN_SIZE)?;
+                self.observe_reads(src.clone());
+                self.audio_pattern.copy_from_slice(&self.memory[src]);
+            }
+            Instruction::LdVxDt { x } => self.v[x as usize] = self.delay_timer,
+            Instruction::LdVxK { x } => {
+                match self.input.iter().position(|&key| key == 1) {
+                    Some(key) => self.v[x as usize] = key as u8,
+                    None => {
+                        if let Some(observer) = self.observer.as_mut() {
+                            observer.on_key_wait(x);
+                        }
+                        return Ok(());
                     }
                 }
-                if !input_pressed {
-                    return;
-                }
             }
-
-            (0xF, _, 0x1, 0x5) => self.delay_timer = self.v[x],
-            (0xF, _, 0x1, 0x8) => self.sound_timer = self.v[x],
-            (0xF, _, 0x1, 0xE) => self.i += self.v[x] as u16,
-            (0xF, _, 0x2, 0x9) => self.i = (self.v[x] * fonts::BYTES_PER_LINE) as u16,
-            (0xF, _, 0x3, 0x3) => {
-                self.memory[self.i as usize] = self.v[x] / 100;
-                self.memory[self.i as usize + 1] = (self.v[x] % 100) / 10;
-                self.memory[self.i as usize + 2] = self.v[x] % 10;
+            Instruction::LdDtVx { x } => self.delay_timer = self.v[x as usize],
+            Instruction::LdStVx { x } => self.sound_timer = self.v[x as usize],
+            Instruction::AddIVx { x } => self.i = self.i.wrapping_add(self.v[x as usize] as u16),
+            Instruction::LdFVx { x } => {
+                self.i = self.font_address + (self.v[x as usize] & 0xF) as u16 * fonts::BYTES_PER_LINE as u16
             }
-            (0xF, _, 0x5, 0x5) => {
-                for register_index in 0..x + 1 {
-                    self.memory[self.i as usize + register_index] = self.v[register_index];
-                }
+            Instruction::PitchVx { x } if self.is_xo_chip() => self.pitch = self.v[x as usize],
+            Instruction::LdHfVx { x } if self.is_super_chip() => {
+                let digit = (self.v[x as usize] & 0xF) as u16;
+                self.i = self.font_address + BIG_FONTS_OFFSET as u16 + digit * fonts::BIG_BYTES_PER_LINE as u16
             }
-            (0xF, _, 0x6, 0x5) => {
-                for register_index in 0..x + 1 {
-                    self.v[register_index] = self.memory[self.i as usize + register_index];
-                }
+            Instruction::LdBVx { x } => {
+                let value = self.v[x as usize];
+                let bcd = self.memory_range(self.i as usize, 3)?;
+                self.memory[bcd.start] = value / 100;
+                self.memory[bcd.start + 1] = (value % 100) / 10;
+                self.memory[bcd.start + 2] = value % 10;
+                self.observe_writes(bcd);
+            }
+            Instruction::LdIVx { x } => {
+                let x = x as usize;
+                let dest = self.memory_range(self.i as usize, x + 1)?;
+                self.memory[dest.clone()].copy_from_slice(&self.v[..=x]);
+                self.observe_writes(dest);
+                self.increment_i_after_load_store(x);
+            }
+            Instruction::LdVxI { x } => {
+                let x = x as usize;
+                let src = self.memory_range(self.i as usize, x + 1)?;
+                self.observe_reads(src.clone());
+                self.v[..=x].copy_from_slice(&self.memory[src]);
+                self.increment_i_after_load_store(x);
+            }
+            Instruction::LdRVx { x } if self.is_super_chip() => {
+                self.rpl[..=x as usize].copy_from_slice(&self.v[..=x as usize])
             }
-            _ => println!(
-                "UNREACHED CODE {:#02X} {} {} {} {} {}",
-                opcode, nnn, nn, x, y, n
-            ),
+            Instruction::LdVxR { x } if self.is_super_chip() => {
+                self.v[..=x as usize].copy_from_slice(&self.rpl[..=x as usize])
+            }
+            _ => return Err(unknown()),
         }
-        self.pc += pc_step;
+        self.pc = self.next_pc(pc_step)?;
+        Ok(())
+    }
+
+    // The address `step` bytes past pc. Running off the top of a 64K memory is an error like
+    // running off the end of a smaller one, instead of wrapping pc back to 0.
+    fn next_pc(&self, step: u16) -> Result<u16, Chip8Error> {
+        self.pc.checked_add(step).ok_or(Chip8Error::MemoryOutOfBounds { addr: self.pc as usize + step as usize })
     }
 
     fn skip_next(&mut self) -> u16 {
+        // XO-CHIP's F000 NNNN is four bytes long and has to be skipped as a whole
+        let next = self.pc as usize + 2;
+        if self.is_xo_chip() && self.memory.get(next..next + 2) == Some(&[0xF0, 0x00]) {
+            return 6;
+        }
         4
     }
+
+    // Applies the stack policy to a CALL with a full stack or a RET with an empty one.
+    // Returns true when the instruction should go ahead with a wrapped stack pointer.
+    fn stack_fault(&mut self, error: Chip8Error) -> Result<bool, Chip8Error> {
+        match self.stack_policy {
+            StackPolicy::Wrap => Ok(true),
+            StackPolicy::Halt => {
+                self.halted = true;
+                self.halt_reason = Some(error);
+                Ok(false)
+            }
+            _ => Err(error),
+        }
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v[y]
+        } else {
+            self.v[x]
+        }
+    }
+
+    fn increment_i_after_load_store(&mut self, x: usize) {
+        if self.quirks.load_store_increments_i {
+            self.i = self.i.wrapping_add(x as u16 + 1);
+        }
+    }
+
+    fn is_super_chip(&self) -> bool {
+        self.instruction_set.has_super_chip()
+    }
+
+    fn is_xo_chip(&self) -> bool {
+        self.instruction_set.has_xo_chip()
+    }
+
+    // Registers VX through VY inclusive, in descending order when X is greater than Y.
+    fn register_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.gfx = vec![0; self.display_width() * self.display_height()];
+        self.draw_flag = true;
+    }
+
+    // Draws `rows` rows of `bytes_per_row * 8` pixels read from I into every selected plane.
+    // With two planes selected the second plane's sprite data follows the first. The starting
+    // coordinate always wraps onto the screen, while pixels that then fall off the right or
+    // bottom edge are clipped, or wrapped with the sprite_wrap quirk. VF only reports
+    // collisions of pixels that were actually drawn. A sprite that runs past the end of memory
+    // fails with MemoryOutOfBounds before anything is drawn or VF is touched.
+    fn draw_sprite(&mut self, x: u8, y: u8, rows: usize, bytes_per_row: usize) -> Result<(), Chip8Error> {
+        let sprite_len = rows * bytes_per_row;
+        let planes: Vec<u8> = [0b01, 0b10].into_iter().filter(|p| self.plane_mask & p != 0).collect();
+        let sprite = self.memory_range(self.i as usize, sprite_len * planes.len())?;
+        self.observe_reads(sprite.clone());
+        let (width, height) = (self.display_width(), self.display_height());
+        let (x, y) = (x as usize % width, y as usize % height);
+
+        self.draw_flag = true;
+        self.vblank = false;
+        self.v[0xF] = 0;
+
+        for (plane_index, plane) in planes.into_iter().enumerate() {
+            let plane_start = sprite.start + plane_index * sprite_len;
+
+            for y_line in 0..rows {
+                let gfx_y = match y + y_line {
+                    gfx_y if gfx_y < height => gfx_y,
+                    gfx_y if self.quirks.sprite_wrap => gfx_y % height,
+                    _ => break,
+                };
+
+                for x_line in 0..bytes_per_row * 8 {
+                    let gfx_x = match x + x_line {
+                        gfx_x if gfx_x < width => gfx_x,
+                        gfx_x if self.quirks.sprite_wrap => gfx_x % width,
+                        _ => break,
+                    };
+
+                    let px = self.memory[plane_start + y_line * bytes_per_row + x_line / 8];
+                    if (px & (0x80 >> (x_line % 8))) != 0 {
+                        let index = gfx_x + gfx_y * width;
+
+                        // if drawing causes any pixel to be erased set the
+                        // collision flag to 1
+                        if self.gfx[index] & plane != 0 {
+                            self.v[0xF] = 1;
+                        }
+
+                        // set pixel value by using XOR
+                        self.gfx[index] ^= plane;
+                    }
+                }
+            }
+        }
+
+        if let Some(observer) = self.observer.as_mut() {
+            observer.on_draw(x as u8, y as u8, bytes_per_row * 8, rows, self.v[0xF] == 1);
+        }
+        Ok(())
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        let width = self.display_width();
+        let lines = lines.min(self.display_height());
+        let len = self.gfx.len();
+
+        let mut scrolled = vec![0; len];
+        scrolled[lines * width..].copy_from_slice(&self.gfx[..len - lines * width]);
+        self.merge_scrolled(scrolled);
+    }
+
+    fn scroll_up(&mut self, lines: usize) {
+        let width = self.display_width();
+        let lines = lines.min(self.display_height());
+        let len = self.gfx.len();
+
+        let mut scrolled = vec![0; len];
+        scrolled[..len - lines * width].copy_from_slice(&self.gfx[lines * width..]);
+        self.merge_scrolled(scrolled);
+    }
+
+    fn scroll_right(&mut self, pixels: usize) {
+        let width = self.display_width();
+        let mut scrolled = vec![0; self.gfx.len()];
+        for (dest, src) in scrolled.chunks_mut(width).zip(self.gfx.chunks(width)) {
+            dest[pixels..].copy_from_slice(&src[..width - pixels]);
+        }
+        self.merge_scrolled(scrolled);
+    }
+
+    fn scroll_left(&mut self, pixels: usize) {
+        let width = self.display_width();
+        let mut scrolled = vec![0; self.gfx.len()];
+        for (dest, src) in scrolled.chunks_mut(width).zip(self.gfx.chunks(width)) {
+            dest[..width - pixels].copy_from_slice(&src[pixels..]);
+        }
+        self.merge_scrolled(scrolled);
+    }
+
+    // Only the selected planes scroll, the others keep their pixels in place.
+    fn merge_scrolled(&mut self, scrolled: Vec<u8>) {
+        let mask = self.plane_mask;
+        for (pixel, scrolled) in self.gfx.iter_mut().zip(scrolled) {
+            *pixel = (*pixel & !mask) | (scrolled & mask);
+        }
+    }
+
+    fn is_key_pressed(&self, key: u8) -> Result<bool, Chip8Error> {
+        match self.input.get(key as usize) {
+            Some(&state) => Ok(state == 1),
+            None => Err(Chip8Error::InvalidKey { key }),
+        }
+    }
+
+    fn observe_reads(&mut self, range: Range<usize>) {
+        if let Some(observer) = self.observer.as_mut() {
+            for addr in range {
+                observer.on_read(addr as u16, self.memory[addr]);
+            }
+        }
+    }
+
+    fn observe_writes(&mut self, range: Range<usize>) {
+        if let Some(observer) = self.observer.as_mut() {
+            for addr in range {
+                observer.on_write(addr as u16, self.memory[addr]);
+            }
+        }
+    }
+
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, Chip8Error> {
+        if start + len > self.memory.len() {
+            return Err(Chip8Error::MemoryOutOfBounds { addr: start.max(self.memory.len()) });
+        }
+        Ok(start..start + len)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::platform::DEFAULT_START_ADDRESS as STARTING_PC_OFFSET;
+    use crate::fonts::FontSet;
 
     #[test]
     fn on_new_all_variables_and_arrays_are_zeroed_out() {
@@ -311,7 +968,7 @@ mod tests {
         c8.gfx.fill(1);
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x00E0);
+        c8.exec_op(0x00E0).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         for bit in c8.gfx {
@@ -327,7 +984,7 @@ mod tests {
         c8.stack[c8.sp as usize - 1] = 255;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x00EE);
+        c8.exec_op(0x00EE).unwrap();
         assert_eq!(c8.pc, 255);
 
         assert_eq!(c8.sp, 1);
@@ -339,7 +996,7 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x3000);
+        c8.exec_op(0x3000).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -349,7 +1006,7 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x3001);
+        c8.exec_op(0x3001).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -359,7 +1016,7 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x4001);
+        c8.exec_op(0x4001).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -369,7 +1026,7 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x4000);
+        c8.exec_op(0x4000).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -384,7 +1041,7 @@ mod tests {
         c8.v[1] = y_val;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x5010);
+        c8.exec_op(0x5010).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -399,7 +1056,7 @@ mod tests {
         c8.v[2] = y_val;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x5020);
+        c8.exec_op(0x5020).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -411,7 +1068,7 @@ mod tests {
         assert_eq!(c8.v[3], 0x00);
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x6312);
+        c8.exec_op(0x6312).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x0012);
@@ -425,7 +1082,7 @@ mod tests {
         c8.v[3] = 0x02;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x7312);
+        c8.exec_op(0x7312).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x02 + 0x0012);
@@ -440,7 +1097,7 @@ mod tests {
         c8.v[4] = 0x03;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8340);
+        c8.exec_op(0x8340).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x03);
@@ -455,7 +1112,7 @@ mod tests {
         c8.v[4] = 0b110;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8341);
+        c8.exec_op(0x8341).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b101 | 0b110);
@@ -470,7 +1127,7 @@ mod tests {
         c8.v[4] = 0b011;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8342);
+        c8.exec_op(0x8342).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b101 & 0b011);
@@ -485,7 +1142,7 @@ mod tests {
         c8.v[4] = 0b011;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8343);
+        c8.exec_op(0x8343).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b101 ^ 0b011);
@@ -501,7 +1158,7 @@ mod tests {
         c8.v[4] = 0x01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8344);
+        c8.exec_op(0x8344).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0xFFu8.wrapping_add(0x01));
@@ -518,7 +1175,7 @@ mod tests {
         c8.v[4] = 0x01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8344);
+        c8.exec_op(0x8344).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0xFE + 0x01);
@@ -535,7 +1192,7 @@ mod tests {
         c8.v[4] = 0x02;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8345);
+        c8.exec_op(0x8345).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x01u8.wrapping_sub(0x02));
@@ -552,7 +1209,7 @@ mod tests {
         c8.v[4] = 0x01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8345);
+        c8.exec_op(0x8345).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x02 - 0x01);
@@ -569,7 +1226,7 @@ mod tests {
         c8.v[4] = 0x01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8345);
+        c8.exec_op(0x8345).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x01 - 0x01);
@@ -585,7 +1242,7 @@ mod tests {
         c8.v[3] = 0b01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8306);
+        c8.exec_op(0x8306).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b00);
@@ -602,7 +1259,7 @@ mod tests {
         c8.v[4] = 0b11;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8347);
+        c8.exec_op(0x8347).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b01);
@@ -619,7 +1276,7 @@ mod tests {
         c8.v[4] = 0x00;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x8347);
+        c8.exec_op(0x8347).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0x00u8.wrapping_sub(0x01));
@@ -635,7 +1292,7 @@ mod tests {
         c8.v[3] = 0b01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x830E);
+        c8.exec_op(0x830E).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[3], 0b10);
@@ -652,7 +1309,7 @@ mod tests {
         c8.v[4] = 2;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x9340);
+        c8.exec_op(0x9340).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -666,7 +1323,7 @@ mod tests {
         c8.v[4] = 2;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0x9340);
+        c8.exec_op(0x9340).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -676,7 +1333,7 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xAEF1);
+        c8.exec_op(0xAEF1).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.i, 0x0EF1);
@@ -690,23 +1347,40 @@ mod tests {
         c8.v[0x0] = 0x01;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xB123);
+        c8.exec_op(0xB123).unwrap();
 
         assert_eq!(c8.pc, 0x01 + 0x0123);
     }
 
-    // #[test]
-    // // Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
-    // fn op_cxnn() {
-    //     let mut c8 = Chip8::new();
-    //
-    //     assert_eq!(c8.pc, STARTING_PC_OFFSET);
-    //     c8.exec_op(0xC133);
-    //
-    //     let mut rng = rng::test::rng(538);
-    //
-    //     assert_eq!(c8.v[1], 123 & 0x0033);
-    // }
+    #[test]
+    // Sets VX to the result of a bitwise and operation on a random number (Typically: 0 to 255) and NN.
+    fn op_cxnn() {
+        let mut c8 = Chip8::new();
+        c8.set_seed(538);
+
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+        c8.exec_op(0xC133).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+
+        let mut rng = Rng::new(538);
+
+        assert_eq!(c8.v[1], rng.next_u8() & 0x0033);
+    }
+
+    #[test]
+    fn same_seed_should_make_cxnn_reproducible() {
+        let mut a = Chip8::new();
+        let mut b = Chip8::new();
+        a.set_seed(7);
+        b.set_seed(7);
+
+        for _ in 0..32 {
+            a.exec_op(0xC0FF).unwrap();
+            b.exec_op(0xC0FF).unwrap();
+            assert_eq!(a.v[0], b.v[0]);
+        }
+        assert_eq!(a.seed(), 7);
+    }
 
     #[test]
     /*
@@ -727,11 +1401,11 @@ mod tests {
             c8.memory[c8.i as usize + i] = 1;
         }
 
-        assert_eq!(c8.is_draw_ready(), false);
+        assert!(!c8.is_draw_ready());
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xD233);
+        c8.exec_op(0xD233).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
-        assert_eq!(c8.is_draw_ready(), true);
+        assert!(c8.is_draw_ready());
 
         c8.debug_gfx_to_stdout();
     }
@@ -747,7 +1421,7 @@ mod tests {
         c8.input[c8.v[1] as usize] = 0;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xE09E);
+        c8.exec_op(0xE09E).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -763,7 +1437,7 @@ mod tests {
         c8.input[c8.v[1] as usize] = 1;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xE09E);
+        c8.exec_op(0xE09E).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -778,7 +1452,7 @@ mod tests {
         c8.input[c8.v[1] as usize] = 1;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xE0A1);
+        c8.exec_op(0xE0A1).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
     }
 
@@ -794,7 +1468,7 @@ mod tests {
         c8.input[c8.v[1] as usize] = 0;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xE0A1);
+        c8.exec_op(0xE0A1).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
     }
 
@@ -807,7 +1481,7 @@ mod tests {
         c8.v[1] = 0;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF107);
+        c8.exec_op(0xF107).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[1], c8.delay_timer);
@@ -819,12 +1493,12 @@ mod tests {
         let mut c8 = Chip8::new();
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF10A);
+        c8.exec_op(0xF10A).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
         assert_eq!(c8.v[1], 0);
 
         c8.input[10] = 1;
-        c8.exec_op(0xF10A);
+        c8.exec_op(0xF10A).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.v[1], 10);
@@ -838,7 +1512,7 @@ mod tests {
         c8.v[2] = 4;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF215);
+        c8.exec_op(0xF215).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.delay_timer, c8.v[2]);
@@ -852,7 +1526,7 @@ mod tests {
         c8.v[2] = 4;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF218);
+        c8.exec_op(0xF218).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.sound_timer, c8.v[2]);
@@ -868,7 +1542,7 @@ mod tests {
         c8.i = 3;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF21E);
+        c8.exec_op(0xF21E).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.i, 3 + 4);
@@ -885,7 +1559,7 @@ mod tests {
         c8.v[1] = font_to_find;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF129);
+        c8.exec_op(0xF129).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.i, (font_to_find * fonts::BYTES_PER_LINE) as u16);
@@ -901,7 +1575,7 @@ mod tests {
         c8.v[1] = 201;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF133);
+        c8.exec_op(0xF133).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         assert_eq!(c8.memory[c8.i as usize], 2);
@@ -925,11 +1599,11 @@ mod tests {
         c8.i = 50;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF455);
+        c8.exec_op(0xF455).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         for i in 0..5 {
-            assert_eq!(c8.memory[c8.i as usize + i as usize], c8.v[i]);
+            assert_eq!(c8.memory[c8.i as usize + i], c8.v[i]);
         }
     }
 
@@ -943,18 +1617,994 @@ mod tests {
 
         c8.i = 50;
 
-        c8.memory[c8.i as usize + 0] = 2;
+        c8.memory[c8.i as usize] = 2;
         c8.memory[c8.i as usize + 1] = 3;
         c8.memory[c8.i as usize + 2] = 5;
         c8.memory[c8.i as usize + 3] = 9;
         c8.memory[c8.i as usize + 4] = 11;
 
         assert_eq!(c8.pc, STARTING_PC_OFFSET);
-        c8.exec_op(0xF465);
+        c8.exec_op(0xF465).unwrap();
         assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
 
         for i in 0..4 + 1 {
-            assert_eq!(c8.v[i], c8.memory[c8.i as usize + i as usize]);
+            assert_eq!(c8.v[i], c8.memory[c8.i as usize + i]);
         }
     }
+
+    #[test]
+    fn load_should_copy_rom_to_starting_pc() {
+        let path = std::env::temp_dir().join("chip8_load_should_copy_rom_to_starting_pc");
+        fs::write(&path, [0x12, 0x34, 0x56]).unwrap();
+
+        let mut c8 = Chip8::new();
+        c8.load(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(&c8.memory[0x200..0x203], &[0x12, 0x34, 0x56]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_should_reject_rom_larger_than_memory() {
+        let path = std::env::temp_dir().join("chip8_load_should_reject_rom_larger_than_memory");
+        fs::write(&path, vec![0; 4096]).unwrap();
+
+        let mut c8 = Chip8::new();
+        let result = c8.load(path.to_str().unwrap());
+
+        assert!(matches!(result, Err(Chip8Error::RomTooLarge { size: 4096, max: 3584 })));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn load_bytes_should_use_the_configured_addresses() {
+        let config = Config { start_address: 0x600, load_address: 0x600, ..Config::default() };
+        let mut c8 = Chip8::with_config(config);
+
+        c8.load_bytes(&[0x60, 0x2A]).unwrap();
+        assert_eq!(&c8.memory[0x600..0x602], &[0x60, 0x2A]);
+        assert!(c8.memory[0x200..0x600].iter().all(|&b| b == 0));
+
+        c8.step_instruction().unwrap();
+        assert_eq!(c8.v[0], 0x2A);
+    }
+
+    #[test]
+    fn load_bytes_should_reject_roms_past_the_end_of_memory() {
+        let mut c8 = Chip8::with_config(Config { load_address: 0xF00, ..Config::default() });
+
+        assert!(c8.load_bytes(&[0xFF; 256]).is_ok());
+        let result = c8.load_bytes(&[0xAA; 257]);
+        assert!(matches!(result, Err(Chip8Error::RomTooLarge { size: 257, max: 256 })));
+        assert_eq!(c8.memory[0xF00], 0xFF);
+
+        let mut c8 = Chip8::with_config(Config { load_address: 0xFFFF, ..Config::default() });
+        assert!(matches!(c8.load_bytes(&[0]), Err(Chip8Error::RomTooLarge { size: 1, max: 0 })));
+    }
+
+    #[test]
+    fn load_from_reader_should_read_until_the_end() {
+        let mut c8 = Chip8::new();
+        c8.load_from_reader(&[0x00, 0xE0, 0x12, 0x00][..]).unwrap();
+
+        assert_eq!(&c8.memory[0x200..0x204], &[0x00, 0xE0, 0x12, 0x00]);
+        assert_eq!(c8.rom_hash(), state::rom_hash(&[0x00, 0xE0, 0x12, 0x00]));
+    }
+
+    #[test]
+    fn load_should_report_missing_file() {
+        let mut c8 = Chip8::new();
+        let result = c8.load("./this/rom/does/not/exist");
+        assert!(matches!(result, Err(Chip8Error::Io(_))));
+    }
+
+    #[test]
+    fn step_instruction_should_report_pc_past_end_of_memory() {
+        let mut c8 = Chip8::new();
+        c8.pc = 0xFFF;
+
+        let result = c8.step_instruction();
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })));
+    }
+
+    #[test]
+    fn unknown_opcode_should_return_error() {
+        let mut c8 = Chip8::new();
+
+        let result = c8.exec_op(0x5001);
+        assert!(matches!(
+            result,
+            Err(Chip8Error::UnknownOpcode { pc: STARTING_PC_OFFSET, opcode: 0x5001 })
+        ));
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+    }
+
+    #[test]
+    fn op_2nnn_should_report_stack_overflow() {
+        let mut c8 = Chip8::new();
+
+        for _ in 0..16 {
+            c8.exec_op(0x2200).unwrap();
+        }
+
+        let result = c8.exec_op(0x2200);
+        assert!(matches!(result, Err(Chip8Error::StackOverflow { pc: STARTING_PC_OFFSET })));
+        assert_eq!(c8.sp, 16);
+    }
+
+    #[test]
+    fn op_00ee_should_report_stack_underflow() {
+        let mut c8 = Chip8::new();
+
+        let result = c8.exec_op(0x00EE);
+        assert!(matches!(result, Err(Chip8Error::StackUnderflow { pc: STARTING_PC_OFFSET })));
+        assert_eq!(c8.sp, 0);
+    }
+
+    fn chip8_with_stack(stack_depth: usize, stack_policy: StackPolicy) -> Chip8 {
+        Chip8::with_config(Config { stack_depth, stack_policy, ..Config::default() })
+    }
+
+    #[test]
+    fn stack_depth_should_be_configurable() {
+        for depth in [1, 12, 255] {
+            let mut c8 = chip8_with_stack(depth, StackPolicy::Error);
+            assert_eq!(c8.stack_depth(), depth);
+
+            for _ in 0..depth {
+                c8.exec_op(0x2200).unwrap();
+            }
+            assert!(matches!(c8.exec_op(0x2200), Err(Chip8Error::StackOverflow { .. })));
+        }
+    }
+
+    #[test]
+    fn try_with_config_should_reject_stack_depths_sp_cannot_address() {
+        for stack_depth in [0, 0x10000] {
+            let result = Chip8::try_with_config(Config { stack_depth, ..Config::default() });
+            assert!(matches!(result, Err(Chip8Error::InvalidConfig(_))));
+        }
+
+        let mut c8 = chip8_with_stack(0xFFFF, StackPolicy::Error);
+        c8.sp = 0xFFFF;
+        assert!(matches!(c8.exec_op(0x2300), Err(Chip8Error::StackOverflow { .. })));
+    }
+
+    #[test]
+    fn stack_policy_halt_should_stop_on_the_faulting_instruction() {
+        let mut c8 = chip8_with_stack(2, StackPolicy::Halt);
+        c8.memory[0x200..0x202].copy_from_slice(&[0x22, 0x00]);
+
+        c8.run_frame(10).unwrap();
+        assert!(c8.is_halted());
+        assert_eq!((c8.pc, c8.sp), (0x200, 2));
+        assert!(matches!(c8.halt_reason(), Some(Chip8Error::StackOverflow { pc: 0x200 })));
+
+        let mut c8 = chip8_with_stack(2, StackPolicy::Halt);
+        c8.exec_op(0x00EE).unwrap();
+        assert!(c8.is_halted());
+        assert_eq!((c8.pc, c8.sp), (STARTING_PC_OFFSET, 0));
+    }
+
+    #[test]
+    fn stack_policy_wrap_should_treat_the_stack_as_a_ring() {
+        let mut c8 = chip8_with_stack(2, StackPolicy::Wrap);
+        for addr in [0x300, 0x400, 0x500] {
+            c8.exec_op(0x2000 | addr).unwrap();
+        }
+        // The third call overwrote the first return address
+        assert_eq!(c8.sp, 1);
+        assert_eq!(c8.stack, vec![0x402, 0x302]);
+
+        c8.exec_op(0x00EE).unwrap();
+        assert_eq!(c8.pc, 0x402);
+        c8.exec_op(0x00EE).unwrap();
+        assert_eq!((c8.pc, c8.sp), (0x302, 1));
+    }
+
+    #[test]
+    fn op_fx55_should_report_memory_out_of_bounds() {
+        let mut c8 = Chip8::new();
+        c8.i = 0xFFE;
+
+        let result = c8.exec_op(0xF455);
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })));
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+    }
+
+    #[test]
+    fn op_dxyn_should_clip_sprites_at_screen_edge() {
+        let mut c8 = Chip8::new();
+
+        c8.v[0] = 60;
+        c8.v[1] = 30;
+        c8.i = 0x300;
+        c8.memory[0x300..0x304].fill(0xFF);
+
+        c8.exec_op(0xD014).unwrap();
+
+        assert_eq!(c8.gfx.iter().filter(|&&p| p == 1).count(), 4 * 2);
+        assert_eq!(c8.gfx[GFX_WIDTH * GFX_HEIGHT - 1], 1);
+    }
+
+    // The pixels a solid sprite drawn at (x, y) should light, computed one pixel at a time
+    fn expected_sprite_pixels(width: usize, height: usize, x: u8, y: u8, sprite: (usize, usize), wrap: bool) -> Vec<u8> {
+        let mut gfx = vec![0; width * height];
+        let (x, y) = (x as usize % width, y as usize % height);
+        for row in 0..sprite.1 {
+            for col in 0..sprite.0 {
+                let (gfx_x, gfx_y) = (x + col, y + row);
+                if wrap {
+                    gfx[gfx_x % width + (gfx_y % height) * width] ^= 1;
+                } else if gfx_x < width && gfx_y < height {
+                    gfx[gfx_x + gfx_y * width] ^= 1;
+                }
+            }
+        }
+        gfx
+    }
+
+    #[test]
+    fn op_dxyn_should_wrap_the_start_and_clip_or_wrap_every_position() {
+        for sprite_wrap in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { sprite_wrap, ..Quirks::default() });
+            c8.i = 0x300;
+            c8.memory[0x300..0x30F].fill(0xFF);
+
+            // Two laps of the screen in each direction, plus the largest values the registers hold
+            for x in (0..128u8).chain([255]) {
+                for y in (0..64u8).chain([255]) {
+                    c8.gfx.fill(0);
+                    c8.v[0] = x;
+                    c8.v[1] = y;
+                    c8.pc = STARTING_PC_OFFSET;
+
+                    c8.exec_op(0xD01F).unwrap();
+                    let expected = expected_sprite_pixels(GFX_WIDTH, GFX_HEIGHT, x, y, (8, 15), sprite_wrap);
+                    assert!(c8.gfx == expected, "sprite at {}, {} with sprite_wrap {}", x, y, sprite_wrap);
+                    assert_eq!(c8.v[0xF], 0);
+
+                    c8.exec_op(0xD01F).unwrap();
+                    assert!(c8.gfx.iter().all(|&p| p == 0));
+                    assert_eq!(c8.v[0xF], 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn op_dxy0_should_wrap_the_start_and_clip_or_wrap_in_hires() {
+        for sprite_wrap in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { sprite_wrap, ..Quirks::default() });
+            c8.exec_op(0x00FF).unwrap();
+            c8.i = 0x300;
+            c8.memory[0x300..0x320].fill(0xFF);
+
+            for x in (0..=255u8).step_by(3) {
+                for y in (0..=255u8).step_by(3) {
+                    c8.gfx.fill(0);
+                    c8.v[0] = x;
+                    c8.v[1] = y;
+
+                    c8.exec_op(0xD010).unwrap();
+                    let expected = expected_sprite_pixels(HIRES_GFX_WIDTH, HIRES_GFX_HEIGHT, x, y, (16, 16), sprite_wrap);
+                    assert!(c8.gfx == expected, "sprite at {}, {} with sprite_wrap {}", x, y, sprite_wrap);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn op_dxyn_should_only_report_collisions_for_drawn_pixels() {
+        for sprite_wrap in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { sprite_wrap, ..Quirks::default() });
+            c8.i = 0x300;
+            c8.memory[0x300] = 0xFF;
+            // Where the part of the sprite past the right edge lands when it wraps
+            c8.gfx[0] = 1;
+            c8.v[0] = 60;
+
+            c8.exec_op(0xD011).unwrap();
+            assert_eq!(c8.v[0xF], sprite_wrap as u8);
+            assert_eq!(c8.gfx[0], !sprite_wrap as u8);
+        }
+    }
+
+    #[test]
+    fn op_dxyn_should_report_sprites_past_the_end_of_memory() {
+        let mut c8 = Chip8::new();
+        c8.i = 0xFFE;
+        c8.v[0xF] = 7;
+
+        let result = c8.exec_op(0xD014);
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x1000 })));
+        assert!(c8.gfx.iter().all(|&p| p == 0));
+        assert_eq!(c8.v[0xF], 7);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+
+        c8.i = 0xFFC;
+        c8.exec_op(0xD014).unwrap();
+    }
+
+    #[test]
+    fn op_ex9e_should_report_invalid_key() {
+        let mut c8 = Chip8::new();
+        c8.v[0] = 0x10;
+
+        let result = c8.exec_op(0xE09E);
+        assert!(matches!(result, Err(Chip8Error::InvalidKey { key: 0x10 })));
+    }
+
+    #[test]
+    // Scrolls the display down by N pixel rows.
+    fn op_00cn() {
+        let mut c8 = Chip8::new();
+        c8.gfx[3] = 1;
+
+        c8.exec_op(0x00C2).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+
+        assert_eq!(c8.gfx[3], 0);
+        assert_eq!(c8.gfx[2 * GFX_WIDTH + 3], 1);
+    }
+
+    #[test]
+    // Scrolls the display right by 4 pixels.
+    fn op_00fb() {
+        let mut c8 = Chip8::new();
+        c8.gfx[GFX_WIDTH] = 1;
+        c8.gfx[GFX_WIDTH - 1] = 1;
+
+        c8.exec_op(0x00FB).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+
+        assert_eq!(c8.gfx[GFX_WIDTH + 4], 1);
+        assert_eq!(c8.gfx.iter().filter(|&&p| p == 1).count(), 1);
+    }
+
+    #[test]
+    // Scrolls the display left by 4 pixels.
+    fn op_00fc() {
+        let mut c8 = Chip8::new();
+        c8.gfx[GFX_WIDTH + 4] = 1;
+        c8.gfx[GFX_WIDTH + 2] = 1;
+
+        c8.exec_op(0x00FC).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+
+        assert_eq!(c8.gfx[GFX_WIDTH], 1);
+        assert_eq!(c8.gfx.iter().filter(|&&p| p == 1).count(), 1);
+    }
+
+    #[test]
+    // Exits the interpreter.
+    fn op_00fd() {
+        let mut c8 = Chip8::new();
+        c8.memory[0x200] = 0x00;
+        c8.memory[0x201] = 0xFD;
+
+        c8.step_instruction().unwrap();
+        assert!(c8.is_halted());
+        assert!(c8.halt_reason().is_none());
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+
+        c8.step_instruction().unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET);
+    }
+
+    #[test]
+    // Switches between low (64x32) and high (128x64) resolution.
+    fn op_00fe_and_00ff() {
+        let mut c8 = Chip8::new();
+
+        c8.exec_op(0x00FF).unwrap();
+        assert!(c8.is_hires());
+        assert_eq!(c8.display_width(), HIRES_GFX_WIDTH);
+        assert_eq!(c8.display_height(), HIRES_GFX_HEIGHT);
+        assert_eq!(c8.gfx.len(), HIRES_GFX_WIDTH * HIRES_GFX_HEIGHT);
+
+        c8.exec_op(0x00FE).unwrap();
+        assert!(!c8.is_hires());
+        assert_eq!(c8.gfx.len(), GFX_WIDTH * GFX_HEIGHT);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+    }
+
+    #[test]
+    // Draws a 16x16 sprite at coordinate (VX, VY) read from 32 bytes starting at I.
+    fn op_dxy0() {
+        let mut c8 = Chip8::new();
+        c8.exec_op(0x00FF).unwrap();
+
+        c8.v[0] = 100;
+        c8.v[1] = 10;
+        c8.i = 0x300;
+        c8.memory[0x300..0x320].fill(0xFF);
+
+        c8.exec_op(0xD010).unwrap();
+        assert_eq!(c8.v[0xF], 0);
+        assert_eq!(c8.gfx.iter().filter(|&&p| p == 1).count(), 16 * 16);
+        assert_eq!(c8.gfx[10 * HIRES_GFX_WIDTH + 115], 1);
+        assert_eq!(c8.gfx[25 * HIRES_GFX_WIDTH + 100], 1);
+
+        c8.exec_op(0xD010).unwrap();
+        assert_eq!(c8.v[0xF], 1);
+        assert!(c8.gfx.iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    // Sets I to the location of the 8x10 sprite for the digit in VX.
+    fn op_fx30() {
+        let mut c8 = Chip8::new();
+        c8.v[1] = 0x0A;
+
+        c8.exec_op(0xF130).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+
+        let i = c8.i as usize;
+        assert_eq!(&c8.memory[i..i + 10], &fonts::BIG_FONTS[100..110]);
+    }
+
+    #[test]
+    fn try_with_config_should_reject_a_font_past_the_end_of_memory() {
+        let result = Chip8::try_with_config(Config { font_address: 0xFFF, ..Config::default() });
+        assert!(matches!(result, Err(Chip8Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn fx29_and_fx30_should_point_into_the_configured_font() {
+        let font = FontSet::SuperChip.font();
+        let mut c8 = Chip8::with_config(Config { font, font_address: 0x050, ..Config::default() });
+        assert!(c8.memory[..0x050].iter().all(|&b| b == 0));
+        c8.v[1] = 0x07;
+
+        c8.exec_op(0xF129).unwrap();
+        assert_eq!(c8.i, 0x050 + 7 * 5);
+        assert_eq!(&c8.memory[c8.i as usize..c8.i as usize + 5], &font.small[35..40]);
+
+        c8.exec_op(0xF130).unwrap();
+        assert_eq!(c8.i, 0x0A0 + 7 * 10);
+        assert_eq!(&c8.memory[c8.i as usize..c8.i as usize + 10], &font.big.unwrap()[70..80]);
+    }
+
+    #[test]
+    // Stores V0 to VX in the RPL user flags and reads them back.
+    fn op_fx75_and_fx85() {
+        let mut c8 = Chip8::new();
+        c8.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        c8.exec_op(0xF375).unwrap();
+        c8.v[..4].fill(0);
+        c8.exec_op(0xF285).unwrap();
+
+        assert_eq!(&c8.v[..4], &[1, 2, 3, 0]);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+    }
+
+    #[test]
+    fn xo_chip_should_have_64k_of_memory() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        assert_eq!(c8.memory.len(), XO_MEMORY_SIZE);
+
+        c8.i = 0xFFF0;
+        c8.v[0] = 0x42;
+        c8.exec_op(0xF055).unwrap();
+        assert_eq!(c8.memory[0xFFF0], 0x42);
+    }
+
+    #[test]
+    fn xo_chip_opcodes_should_be_unknown_without_xo_chip() {
+        let mut c8 = Chip8::new();
+
+        for opcode in [0x5012, 0x5013, 0xF101, 0xF002, 0xF13A, 0xF000] {
+            let result = c8.exec_op(opcode);
+            assert!(matches!(result, Err(Chip8Error::UnknownOpcode { .. })), "{:#06X}", opcode);
+        }
+    }
+
+    #[test]
+    // Loads I with the 16 bit address NNNN stored in the next two bytes.
+    fn op_f000_nnnn() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.memory[0x202] = 0xAB;
+        c8.memory[0x203] = 0xCD;
+
+        c8.exec_op(0xF000).unwrap();
+        assert_eq!(c8.i, 0xABCD);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+    }
+
+    #[test]
+    // Skipping over F000 NNNN has to skip all four of its bytes.
+    fn skip_should_skip_over_f000_nnnn() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.memory[0x202] = 0xF0;
+        c8.memory[0x203] = 0x00;
+
+        c8.exec_op(0x3000).unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 6);
+    }
+
+    #[test]
+    // Saves VX to VY in memory starting at I, without changing I. X may be greater than Y.
+    fn op_5xy2() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.v[1..4].copy_from_slice(&[1, 2, 3]);
+        c8.i = 0x300;
+
+        c8.exec_op(0x5132).unwrap();
+        assert_eq!(&c8.memory[0x300..0x303], &[1, 2, 3]);
+
+        c8.exec_op(0x5312).unwrap();
+        assert_eq!(&c8.memory[0x300..0x303], &[3, 2, 1]);
+        assert_eq!(c8.i, 0x300);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+    }
+
+    #[test]
+    // Loads VX to VY from memory starting at I, without changing I. X may be greater than Y.
+    fn op_5xy3() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.memory[0x300..0x303].copy_from_slice(&[1, 2, 3]);
+        c8.i = 0x300;
+
+        c8.exec_op(0x5243).unwrap();
+        assert_eq!(&c8.v[2..5], &[1, 2, 3]);
+
+        c8.exec_op(0x5423).unwrap();
+        assert_eq!(&c8.v[2..5], &[3, 2, 1]);
+        assert_eq!(c8.i, 0x300);
+    }
+
+    #[test]
+    // Selects the bitplanes that drawing, clearing and scrolling apply to.
+    fn op_fn01_should_draw_into_selected_planes() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.i = 0x300;
+        c8.memory[0x300] = 0x80;
+        c8.memory[0x301] = 0xC0;
+
+        c8.exec_op(0xF201).unwrap();
+        c8.exec_op(0xD001).unwrap();
+        assert_eq!(c8.gfx[0], 0b10);
+
+        c8.exec_op(0xF301).unwrap();
+        c8.exec_op(0xD001).unwrap();
+        assert_eq!(c8.v[0xF], 1);
+        assert_eq!(c8.gfx[0], 0b01);
+        assert_eq!(c8.gfx[1], 0b10);
+
+        c8.exec_op(0xF001).unwrap();
+        c8.exec_op(0xD001).unwrap();
+        assert_eq!(c8.v[0xF], 0);
+        assert_eq!(c8.gfx[0], 0b01);
+    }
+
+    #[test]
+    fn op_00e0_should_only_clear_selected_planes() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.gfx.fill(0b11);
+
+        c8.exec_op(0xF201).unwrap();
+        c8.exec_op(0x00E0).unwrap();
+
+        assert!(c8.gfx.iter().all(|&p| p == 0b01));
+    }
+
+    #[test]
+    fn scrolling_should_only_move_selected_planes() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.gfx[0] = 0b11;
+
+        c8.exec_op(0xF101).unwrap();
+        c8.exec_op(0x00FB).unwrap();
+
+        assert_eq!(c8.gfx[0], 0b10);
+        assert_eq!(c8.gfx[4], 0b01);
+    }
+
+    #[test]
+    // Loads 16 bytes starting at I into the audio pattern buffer.
+    fn op_f002() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        c8.i = 0x300;
+        for i in 0..AUDIO_PATTERN_SIZE {
+            c8.memory[0x300 + i] = i as u8;
+        }
+
+        c8.exec_op(0xF002).unwrap();
+        assert_eq!(c8.audio_pattern()[15], 15);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+    }
+
+    #[test]
+    // Sets the audio pattern playback pitch to VX.
+    fn op_fx3a() {
+        let mut c8 = Chip8::with_instruction_set(InstructionSet::XoChip);
+        assert_eq!(c8.audio_playback_rate(), 4000.0);
+
+        c8.v[1] = 112;
+        c8.exec_op(0xF13A).unwrap();
+        assert_eq!(c8.audio_playback_rate(), 8000.0);
+    }
+
+    fn chip8_with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::with_quirks(InstructionSet::SuperChip11, quirks)
+    }
+
+    #[test]
+    fn quirk_shift_uses_vy() {
+        for shift_uses_vy in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+            c8.v[3] = 0b0000_0100;
+            c8.v[4] = 0b1000_0011;
+
+            c8.exec_op(0x8346).unwrap();
+            if shift_uses_vy {
+                assert_eq!((c8.v[3], c8.v[0xF]), (0b0100_0001, 1));
+            } else {
+                assert_eq!((c8.v[3], c8.v[0xF]), (0b0000_0010, 0));
+            }
+
+            c8.v[3] = 0b0000_0100;
+            c8.exec_op(0x834E).unwrap();
+            if shift_uses_vy {
+                assert_eq!((c8.v[3], c8.v[0xF]), (0b0000_0110, 1));
+            } else {
+                assert_eq!((c8.v[3], c8.v[0xF]), (0b0000_1000, 0));
+            }
+        }
+    }
+
+    #[test]
+    // The flag is written after the result, so shifting into VF leaves the flag.
+    fn op_8xy6_should_set_vf_last() {
+        let mut c8 = Chip8::new();
+        c8.v[0xF] = 0b11;
+
+        c8.exec_op(0x8F06).unwrap();
+        assert_eq!(c8.v[0xF], 1);
+    }
+
+    #[test]
+    // Octo compiles comparisons to `vf := vx  vf -= vy` and reads the flag back from VF.
+    fn op_8xy4_8xy5_8xy7_should_set_vf_last() {
+        let mut c8 = Chip8::new();
+        c8.v[0xF] = 0xFF;
+        c8.v[1] = 1;
+        c8.exec_op(0x8F14).unwrap();
+        assert_eq!(c8.v[0xF], 1);
+
+        c8.v[0xF] = 5;
+        c8.v[1] = 3;
+        c8.exec_op(0x8F15).unwrap();
+        assert_eq!(c8.v[0xF], 1);
+
+        c8.v[0xF] = 5;
+        c8.exec_op(0x8F17).unwrap();
+        assert_eq!(c8.v[0xF], 0);
+    }
+
+    #[test]
+    fn quirk_load_store_increments_i() {
+        for load_store_increments_i in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { load_store_increments_i, ..Quirks::default() });
+            c8.i = 0x300;
+
+            c8.exec_op(0xF255).unwrap();
+            let expected = if load_store_increments_i { 0x303 } else { 0x300 };
+            assert_eq!(c8.i, expected);
+
+            c8.exec_op(0xF165).unwrap();
+            let expected = if load_store_increments_i { 0x305 } else { 0x300 };
+            assert_eq!(c8.i, expected);
+        }
+    }
+
+    #[test]
+    fn quirk_jump_uses_vx() {
+        for jump_uses_vx in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { jump_uses_vx, ..Quirks::default() });
+            c8.v[0] = 0x01;
+            c8.v[3] = 0x10;
+
+            c8.exec_op(0xB345).unwrap();
+            let expected = if jump_uses_vx { 0x355 } else { 0x346 };
+            assert_eq!(c8.pc, expected);
+        }
+    }
+
+    #[test]
+    fn quirk_logic_resets_vf() {
+        for logic_resets_vf in [false, true] {
+            for opcode in [0x8121, 0x8122, 0x8123] {
+                let mut c8 = chip8_with_quirks(Quirks { logic_resets_vf, ..Quirks::default() });
+                c8.v[0xF] = 0xAA;
+
+                c8.exec_op(opcode).unwrap();
+                let expected = if logic_resets_vf { 0 } else { 0xAA };
+                assert_eq!(c8.v[0xF], expected, "{:#06X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn quirk_sprite_wrap() {
+        for sprite_wrap in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { sprite_wrap, ..Quirks::default() });
+            c8.v[0] = 62;
+            c8.v[1] = 31;
+            c8.i = 0x300;
+            c8.memory[0x300..0x302].fill(0xF0);
+
+            c8.exec_op(0xD012).unwrap();
+
+            let lit = c8.gfx.iter().filter(|&&p| p == 1).count();
+            if sprite_wrap {
+                assert_eq!(lit, 8);
+                assert_eq!(c8.gfx[0], 1);
+                assert_eq!(c8.gfx[1], 1);
+                assert_eq!(c8.gfx[31 * GFX_WIDTH + 63], 1);
+            } else {
+                assert_eq!(lit, 2);
+                assert_eq!(c8.gfx[0], 0);
+            }
+        }
+    }
+
+    #[test]
+    fn quirk_display_wait() {
+        for display_wait in [false, true] {
+            let mut c8 = chip8_with_quirks(Quirks { display_wait, ..Quirks::default() });
+            c8.memory[0x200..0x206].copy_from_slice(&[0xD0, 0x01, 0xD0, 0x01, 0x12, 0x04]);
+
+            c8.run_frame(10).unwrap();
+            let expected = if display_wait { STARTING_PC_OFFSET + 2 } else { STARTING_PC_OFFSET + 4 };
+            assert_eq!(c8.pc, expected);
+
+            c8.run_frame(10).unwrap();
+            assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+        }
+    }
+
+    #[test]
+    fn with_platform_should_size_the_stack() {
+        let mut c8 = Chip8::with_platform(Platform::CosmacVip);
+
+        for _ in 0..12 {
+            c8.exec_op(0x2200).unwrap();
+        }
+
+        let result = c8.exec_op(0x2200);
+        assert!(matches!(result, Err(Chip8Error::StackOverflow { .. })));
+    }
+
+    #[test]
+    fn with_config_should_start_at_the_configured_address() {
+        let c8 = Chip8::with_config(Config { start_address: 0x600, ..Config::default() });
+        assert_eq!(c8.pc, 0x600);
+    }
+
+    #[test]
+    fn chip8_instruction_set_should_reject_super_chip_opcodes() {
+        let mut c8 = Chip8::with_platform(Platform::CosmacVip);
+
+        for opcode in [0x00C1, 0x00FB, 0x00FC, 0x00FD, 0x00FE, 0x00FF, 0xF130, 0xF175, 0xF185] {
+            let result = c8.exec_op(opcode);
+            assert!(matches!(result, Err(Chip8Error::UnknownOpcode { .. })), "{:#06X}", opcode);
+        }
+
+        assert!(c8.memory[BIG_FONTS_OFFSET..BIG_FONTS_OFFSET + fonts::BIG_FONTS.len()].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn super_chip_10_should_reject_scrolling() {
+        let mut c8 = Chip8::with_platform(Platform::SChip10);
+
+        for opcode in [0x00C1, 0x00FB, 0x00FC] {
+            let result = c8.exec_op(opcode);
+            assert!(matches!(result, Err(Chip8Error::UnknownOpcode { .. })), "{:#06X}", opcode);
+        }
+        c8.exec_op(0x00FF).unwrap();
+    }
+
+    #[test]
+    // Scrolls the selected planes up by N pixel rows.
+    fn op_00dn() {
+        let mut c8 = Chip8::with_platform(Platform::XoChip);
+        c8.gfx[3 * GFX_WIDTH] = 1;
+
+        c8.exec_op(0x00D2).unwrap();
+        assert_eq!(c8.gfx[GFX_WIDTH], 1);
+        assert_eq!(c8.gfx[3 * GFX_WIDTH], 0);
+    }
+
+    #[test]
+    // Calls the subroutine at NNN, which returns to the instruction after the call.
+    fn op_2nnn_and_00ee_should_return_after_the_call() {
+        let mut c8 = Chip8::new();
+        c8.memory[0x200..0x202].copy_from_slice(&[0x23, 0x00]);
+        c8.memory[0x300..0x302].copy_from_slice(&[0x00, 0xEE]);
+
+        c8.step_instruction().unwrap();
+        assert_eq!(c8.pc, 0x300);
+        assert_eq!(c8.sp, 1);
+
+        c8.step_instruction().unwrap();
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 2);
+        assert_eq!(c8.sp, 0);
+    }
+
+    #[test]
+    fn accessors_should_expose_the_machine_state() {
+        let mut c8 = Chip8::new();
+        c8.v[4] = 0x12;
+        c8.stack[0] = 0x2A4;
+        c8.sp = 1;
+        c8.sound_timer = 3;
+
+        assert_eq!(c8.registers()[4], 0x12);
+        assert_eq!(c8.stack(), &[0x2A4]);
+        assert_eq!(c8.memory().len(), MEMORY_SIZE);
+        assert_eq!(c8.pc(), STARTING_PC_OFFSET);
+        assert!(c8.is_sound_playing());
+
+        c8.tick_timers();
+        c8.tick_timers();
+        c8.tick_timers();
+        assert_eq!(c8.sound_timer(), 0);
+        assert!(!c8.is_sound_playing());
+    }
+
+    #[test]
+    fn setters_should_validate_their_arguments() {
+        let mut c8 = Chip8::new();
+
+        c8.set_register(0xF, 1).unwrap();
+        assert_eq!(c8.registers()[0xF], 1);
+        assert!(matches!(c8.set_register(16, 1), Err(Chip8Error::InvalidRegister { x: 16 })));
+
+        c8.set_pc(0x300).unwrap();
+        assert_eq!(c8.pc(), 0x300);
+        assert!(c8.set_pc(0xFFF).is_err());
+        assert_eq!(c8.pc(), 0x300);
+
+        c8.write_memory(0x400, &[1, 2, 3]).unwrap();
+        assert_eq!(&c8.memory()[0x400..0x403], &[1, 2, 3]);
+        assert!(c8.write_memory(0xFFE, &[1, 2, 3]).is_err());
+        assert_eq!(&c8.memory()[0xFFE..], &[0, 0]);
+
+        c8.set_stack(&[0x202, 0x204]).unwrap();
+        assert_eq!(c8.stack(), &[0x202, 0x204]);
+        assert!(c8.set_stack(&[0; 17]).is_err());
+    }
+
+    #[test]
+    fn instructions_at_the_top_of_64k_memory_should_not_overflow_pc() {
+        let mut c8 = Chip8::with_platform(Platform::XoChip);
+        c8.pc = 0xFFFE;
+        c8.memory[0xFFFE..].copy_from_slice(&[0x70, 0x01]);
+        let result = c8.step_instruction();
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x10000 })));
+        assert_eq!((c8.pc, c8.v[0]), (0xFFFE, 1));
+
+        c8.memory[0xFFFE..].copy_from_slice(&[0x23, 0x00]);
+        let result = c8.step_instruction();
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x10000 })));
+        assert_eq!((c8.pc, c8.sp), (0xFFFE, 0));
+
+        // F000 NNNN with its operand in the last two bytes
+        c8.pc = 0xFFFC;
+        c8.memory[0xFFFC..].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        let result = c8.step_instruction();
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x10000 })));
+        assert_eq!(c8.i, 0x1234);
+
+        // A skip over the last instruction
+        c8.pc = 0xFFFC;
+        c8.memory[0xFFFC..0xFFFE].copy_from_slice(&[0x30, 0x01]);
+        let result = c8.step_instruction();
+        assert!(matches!(result, Err(Chip8Error::MemoryOutOfBounds { addr: 0x10000 })));
+    }
+
+    #[test]
+    fn step_instruction_should_not_touch_timers() {
+        let mut c8 = Chip8::new();
+        c8.delay_timer = 5;
+        c8.sound_timer = 5;
+        c8.memory[0x200..0x202].copy_from_slice(&[0x12, 0x00]);
+
+        c8.step_instruction().unwrap();
+        assert_eq!((c8.delay_timer, c8.sound_timer), (5, 5));
+
+        c8.tick_timers();
+        assert_eq!((c8.delay_timer, c8.sound_timer), (4, 4));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn tick_should_still_run_one_instruction_per_timer_tick() {
+        let mut c8 = Chip8::new();
+        c8.delay_timer = 5;
+        // ADD V0, 1; ADD V0, 1
+        c8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x70, 0x01]);
+
+        c8.tick().unwrap();
+        c8.tick().unwrap();
+        assert_eq!(c8.v[0], 2);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 4);
+        assert_eq!(c8.delay_timer, 3);
+    }
+
+    #[test]
+    fn tick_timers_should_stop_at_zero() {
+        let mut c8 = Chip8::new();
+        c8.delay_timer = 1;
+
+        c8.tick_timers();
+        c8.tick_timers();
+        assert_eq!((c8.delay_timer, c8.sound_timer), (0, 0));
+    }
+
+    #[test]
+    fn instruction_at_should_decode_the_word_in_memory() {
+        let mut c8 = Chip8::new();
+        c8.memory[0x2A4..0x2A6].copy_from_slice(&[0xD1, 0x25]);
+
+        assert_eq!(c8.instruction_at(0x2A4), Some(Instruction::Drw { x: 1, y: 2, n: 5 }));
+        assert_eq!(c8.instruction_at(0x2A6), Some(Instruction::Sys(0)));
+        assert_eq!(c8.instruction_at(MEMORY_SIZE as u16 - 1), None);
+    }
+
+    #[test]
+    fn tick_timers_should_count_down_at_the_configured_rate() {
+        for (timer_hz, frames, expected) in [(60, 60, 60), (120, 30, 60), (30, 60, 30), (50, 6, 5), (1000, 3, 50)] {
+            let mut c8 = Chip8::with_config(Config { timer_hz, ..Config::default() });
+            c8.delay_timer = 255;
+            c8.sound_timer = 255;
+
+            for _ in 0..frames {
+                c8.tick_timers();
+            }
+            assert_eq!(c8.delay_timer, 255 - expected, "{} Hz", timer_hz);
+            assert_eq!(c8.sound_timer, 255 - expected, "{} Hz", timer_hz);
+        }
+    }
+
+    #[test]
+    fn run_frame_should_execute_cycles_then_tick_timers_once() {
+        let mut c8 = Chip8::new();
+        c8.delay_timer = 10;
+        // ADD V0, 1 repeated
+        for addr in (0x200..0x220).step_by(2) {
+            c8.memory[addr..addr + 2].copy_from_slice(&[0x70, 0x01]);
+        }
+
+        c8.run_frame(7).unwrap();
+        assert_eq!(c8.v[0], 7);
+        assert_eq!(c8.pc, STARTING_PC_OFFSET + 14);
+        assert_eq!(c8.delay_timer, 9);
+    }
+
+    #[test]
+    fn run_frame_should_report_whether_the_display_changed() {
+        let mut c8 = Chip8::new();
+        c8.memory[0x200..0x206].copy_from_slice(&[0x00, 0xE0, 0x12, 0x02, 0x12, 0x02]);
+
+        c8.run_frame(2).unwrap();
+        assert!(c8.is_draw_ready());
+
+        c8.run_frame(2).unwrap();
+        assert!(!c8.is_draw_ready());
+    }
+
+    #[test]
+    fn run_frame_should_stop_when_halted() {
+        let mut c8 = Chip8::new();
+        c8.delay_timer = 2;
+        c8.memory[0x200..0x202].copy_from_slice(&[0x00, 0xFD]);
+
+        c8.run_frame(10).unwrap();
+        assert!(c8.is_halted());
+        assert_eq!(c8.delay_timer, 1);
+    }
 }
@@ -0,0 +1,728 @@
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::observer::Observer;
+use crate::platform::DEFAULT_START_ADDRESS;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// A value a `Condition` can compare.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    /// The byte at an address, written `[0x300]`.
+    Memory(u16),
+    Value(u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A comparison such as `V3 == 10` or `[300] > I`, checked before each instruction. Numbers
+/// are hex like the addresses of listings, so `V3 == 10` compares against 0x10.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub lhs: Operand,
+    pub comparison: Comparison,
+    pub rhs: Operand,
+}
+
+impl Condition {
+    pub fn holds(&self, c8: &Chip8) -> bool {
+        let (lhs, rhs) = (self.lhs.value(c8), self.rhs.value(c8));
+        match self.comparison {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let comparison = match self.comparison {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        };
+        write!(f, "{} {} {}", self.lhs, comparison, self.rhs)
+    }
+}
+
+impl Operand {
+    fn value(&self, c8: &Chip8) -> u16 {
+        match *self {
+            Operand::V(x) => c8.v[x as usize] as u16,
+            Operand::I => c8.i,
+            Operand::Pc => c8.pc,
+            Operand::Sp => c8.sp,
+            Operand::Dt => c8.delay_timer as u16,
+            Operand::St => c8.sound_timer as u16,
+            Operand::Memory(addr) => c8.memory.get(addr as usize).copied().unwrap_or(0) as u16,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::V(x) => write!(f, "V{:X}", x),
+            Operand::I => write!(f, "I"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::Sp => write!(f, "SP"),
+            Operand::Dt => write!(f, "DT"),
+            Operand::St => write!(f, "ST"),
+            Operand::Memory(addr) => write!(f, "[0x{:03X}]", addr),
+            Operand::Value(value) => write!(f, "0x{:02X}", value),
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.trim().to_ascii_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Operand::I,
+            "PC" => Operand::Pc,
+            "SP" => Operand::Sp,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            _ => {
+                if let Some(addr) = upper.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                    Operand::Memory(parse_number(addr)?)
+                } else if let Some(x) = upper.strip_prefix('V').filter(|x| x.len() == 1) {
+                    Operand::V(u8::from_str_radix(x, 16).map_err(|_| format!("'{}' is not a register", s))?)
+                } else {
+                    Operand::Value(parse_number(&upper)?)
+                }
+            }
+        };
+        Ok(operand)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two character operators first so `<=` is not read as `<`
+        let operators = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        for (symbol, comparison) in operators {
+            if let Some((lhs, rhs)) = s.split_once(symbol) {
+                return Ok(Condition { lhs: lhs.parse()?, comparison, rhs: rhs.parse()? });
+            }
+        }
+        Err(format!("'{}' is not a comparison", s.trim()))
+    }
+}
+
+/// Parses hex, `2A4`, `0x2A4` or `#2A4`.
+fn parse_number(s: &str) -> Result<u16, String> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let hex = lower.strip_prefix("0x").or(lower.strip_prefix('#')).unwrap_or(&lower);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex number", s))
+}
+
+/// Stops when pc reaches `addr` and `condition` holds. Either may be left out, but not both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Stops after an instruction reads or writes any of `len` bytes from `addr`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// Why the debugger paused. `id` is the one `add_breakpoint` or `add_watchpoint` returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Breakpoint { id: usize, pc: u16 },
+    Watchpoint { id: usize, pc: u16, addr: u16, access: Access },
+    Step { pc: u16 },
+    Halted { pc: u16 },
+    /// A CALL with a full stack halted the machine under `StackPolicy::Halt`.
+    StackOverflow { pc: u16 },
+    /// A RET with an empty stack halted the machine under `StackPolicy::Halt`.
+    StackUnderflow { pc: u16 },
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint { id, pc } => write!(f, "breakpoint {} at {:03X}", id, pc),
+            Stop::Watchpoint { id, pc, addr, access } => {
+                let verb = if *access == Access::Read { "read" } else { "write" };
+                write!(f, "watchpoint {}: {} of {:03X} by the instruction at {:03X}", id, verb, addr, pc)
+            }
+            Stop::Step { pc } => write!(f, "stepped to {:03X}", pc),
+            Stop::Halted { pc } => write!(f, "halted at {:03X}", pc),
+            Stop::StackOverflow { pc } => write!(f, "stack overflow at {:03X}", pc),
+            Stop::StackUnderflow { pc } => write!(f, "stack underflow at {:03X}", pc),
+        }
+    }
+}
+
+/// How execution continues after `resume`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    /// Until a breakpoint or watchpoint.
+    Continue,
+    /// One instruction.
+    Step,
+    /// One instruction, running a CALL until it returns.
+    StepOver,
+    /// Until the current subroutine returns.
+    StepOut,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Paused,
+    Continue,
+    Step,
+    // Running until the stack pointer drops back to this depth
+    UntilDepth(u16),
+}
+
+/// Breakpoints, watchpoints and stepping over a `Chip8`. Like the machine itself it runs a
+/// frame at a time, so a frontend can keep drawing while the debugger runs or is paused.
+pub struct Debugger {
+    breakpoints: Vec<(usize, Breakpoint)>,
+    watchpoints: Vec<(usize, Watchpoint)>,
+    next_id: usize,
+    state: State,
+    // The pc execution resumed at, which must not hit its own breakpoint again
+    resumed_at: Option<u16>,
+    // Wraps the machine's observer for as long as there are watchpoints, see `sync_recorder`
+    recorder: Option<Rc<RefCell<AccessRecorder>>>,
+}
+
+impl Default for Debugger {
+    fn default() -> Self {
+        Debugger::new()
+    }
+}
+
+impl Debugger {
+    /// Creates a debugger that starts paused.
+    pub fn new() -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            next_id: 1,
+            state: State::Paused,
+            resumed_at: None,
+            recorder: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.take_id();
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// While there are watchpoints the debugger wraps the machine's observer to see the
+    /// accesses instructions make, so attach an observer with `Chip8::set_observer` first.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.take_id();
+        self.watchpoints.push((id, watchpoint));
+        id
+    }
+
+    /// Removes the breakpoint or watchpoint with `id`, returning false if there is none.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let count = self.breakpoints.len() + self.watchpoints.len();
+        self.breakpoints.retain(|(i, _)| *i != id);
+        self.watchpoints.retain(|(i, _)| *i != id);
+        count != self.breakpoints.len() + self.watchpoints.len()
+    }
+
+    /// Removes every breakpoint and watchpoint.
+    pub fn clear(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> &[(usize, Breakpoint)] {
+        &self.breakpoints
+    }
+
+    pub fn watchpoints(&self) -> &[(usize, Watchpoint)] {
+        &self.watchpoints
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state == State::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.state = State::Paused;
+    }
+
+    pub fn resume(&mut self, c8: &Chip8, resume: Resume) {
+        let calling = matches!(c8.instruction_at(c8.pc), Some(Instruction::Call(_)));
+        self.state = match resume {
+            Resume::Continue => State::Continue,
+            Resume::Step => State::Step,
+            Resume::StepOver if calling => State::UntilDepth(c8.sp),
+            Resume::StepOver => State::Step,
+            Resume::StepOut => State::UntilDepth(c8.sp.saturating_sub(1)),
+        };
+        self.resumed_at = Some(c8.pc);
+    }
+
+    /// Like `Chip8::run_frame`, but pauses before an instruction at a breakpoint and after
+    /// an instruction that trips a watchpoint or completes a step. Timers only tick when the
+    /// whole frame ran. Does nothing while paused.
+    pub fn run_frame(&mut self, c8: &mut Chip8, cycles_per_frame: u32) -> Result<Option<Stop>, Chip8Error> {
+        if self.is_paused() {
+            return Ok(None);
+        }
+        c8.draw_flag = false;
+
+        for _ in 0..cycles_per_frame {
+            if let Some(stop) = self.step(c8)? {
+                self.state = State::Paused;
+                return Ok(Some(stop));
+            }
+        }
+
+        c8.tick_timers();
+        Ok(None)
+    }
+
+    fn step(&mut self, c8: &mut Chip8) -> Result<Option<Stop>, Chip8Error> {
+        let pc = c8.pc;
+        if c8.halted {
+            return Ok(Some(Stop::Halted { pc }));
+        }
+        if self.resumed_at.take() != Some(pc) {
+            if let Some(id) = self.breakpoint_at(c8) {
+                return Ok(Some(Stop::Breakpoint { id, pc }));
+            }
+        }
+
+        self.sync_recorder(c8);
+        if let Some(recorder) = &self.recorder {
+            recorder.borrow_mut().accesses.clear();
+        }
+        if let Err(e) = c8.step_instruction() {
+            self.state = State::Paused;
+            return Err(e);
+        }
+        if c8.halted {
+            return Ok(Some(match c8.halt_reason() {
+                Some(Chip8Error::StackOverflow { .. }) => Stop::StackOverflow { pc },
+                Some(Chip8Error::StackUnderflow { .. }) => Stop::StackUnderflow { pc },
+                _ => Stop::Halted { pc },
+            }));
+        }
+
+        let recorder = self.recorder.as_ref().map(|recorder| recorder.borrow());
+        let accesses = recorder.as_ref().map_or(&[][..], |recorder| &recorder.accesses[..]);
+        for (id, watchpoint) in &self.watchpoints {
+            let range = watchpoint.addr as usize..watchpoint.addr as usize + watchpoint.len as usize;
+            for (enabled, access) in [(watchpoint.write, Access::Write), (watchpoint.read, Access::Read)] {
+                let hit = accesses.iter().find(|&&(addr, a)| enabled && a == access && range.contains(&(addr as usize)));
+                if let Some(&(addr, _)) = hit {
+                    return Ok(Some(Stop::Watchpoint { id: *id, pc, addr, access }));
+                }
+            }
+        }
+
+        let done = match self.state {
+            State::Step => true,
+            State::UntilDepth(depth) => c8.sp <= depth,
+            _ => false,
+        };
+        Ok(done.then_some(Stop::Step { pc: c8.pc }))
+    }
+
+    // Watchpoints see the accesses instructions really make through the observer hooks. The
+    // recorder goes in once the first watchpoint exists and comes out once the last is gone.
+    fn sync_recorder(&mut self, c8: &mut Chip8) {
+        if self.watchpoints.is_empty() {
+            if let Some(recorder) = self.recorder.take() {
+                let inner = recorder.borrow_mut().inner.take();
+                c8.set_observer(inner);
+            }
+        } else if self.recorder.is_none() {
+            let recorder = Rc::new(RefCell::new(AccessRecorder { inner: c8.take_observer(), accesses: Vec::new() }));
+            c8.set_observer(Some(Box::new(recorder.clone())));
+            self.recorder = Some(recorder);
+        }
+    }
+
+    fn breakpoint_at(&self, c8: &Chip8) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|(_, bp)| {
+                bp.addr.is_none_or(|addr| addr == c8.pc) && bp.condition.is_none_or(|c| c.holds(c8))
+            })
+            .map(|(id, _)| *id)
+    }
+
+    fn take_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    /// Registers, I, pc, sp and timers, one line each for V and the rest.
+    pub fn format_registers(c8: &Chip8) -> String {
+        let v: Vec<String> = c8.v.iter().enumerate().map(|(x, v)| format!("V{:X}={:02X}", x, v)).collect();
+        format!(
+            "{}\nI={:03X} PC={:03X} SP={:X} DT={:02X} ST={:02X}",
+            v.join(" "),
+            c8.i,
+            c8.pc,
+            c8.sp,
+            c8.delay_timer,
+            c8.sound_timer
+        )
+    }
+
+    /// The return addresses on the stack, innermost first.
+    pub fn format_stack(c8: &Chip8) -> String {
+        if c8.sp == 0 {
+            return "stack is empty".to_string();
+        }
+        (0..c8.sp as usize)
+            .rev()
+            .map(|depth| format!("#{} {:03X}", depth, c8.stack[depth]))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// `len` bytes from `addr`, sixteen to a line.
+    pub fn format_memory(c8: &Chip8, addr: u16, len: u16) -> String {
+        let start = (addr as usize).min(c8.memory.len());
+        let end = (start + len as usize).min(c8.memory.len());
+        c8.memory[start..end]
+            .chunks(16)
+            .enumerate()
+            .map(|(row, bytes)| {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                format!("{:03X}: {}", start + row * 16, hex.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Decodes `before` words before pc and `after` words after it, marking pc with `=>`.
+    pub fn format_disassembly(c8: &Chip8, before: u16, after: u16) -> String {
+        let start = c8.pc.saturating_sub(before * 2).max(DEFAULT_START_ADDRESS.min(c8.pc));
+        let mut lines = Vec::new();
+        let mut addr = start;
+        while addr <= c8.pc.saturating_add(after * 2) && (addr as usize) + 1 < c8.memory.len() {
+            let marker = if addr == c8.pc { "=>" } else { "  " };
+            let opcode = (c8.memory[addr as usize] as u16) << 8 | c8.memory[addr as usize + 1] as u16;
+            let text = match Instruction::decode(opcode) {
+                Some(instruction) => instruction.to_string(),
+                None => format!("DW #{:04X}", opcode),
+            };
+            lines.push(format!("{} {:03X}: {:04X}  {}", marker, addr, opcode, text));
+            addr += 2;
+        }
+        lines.join("\n")
+    }
+}
+
+// Collects the addresses an instruction reads and writes while passing every callback on to
+// the observer the frontend attached
+struct AccessRecorder {
+    inner: Option<Box<dyn Observer>>,
+    accesses: Vec<(u16, Access)>,
+}
+
+impl Observer for AccessRecorder {
+    fn on_execute(&mut self, pc: u16, instruction: Instruction) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.on_execute(pc, instruction);
+        }
+    }
+
+    fn on_read(&mut self, addr: u16, value: u8) {
+        self.accesses.push((addr, Access::Read));
+        if let Some(inner) = self.inner.as_mut() {
+            inner.on_read(addr, value);
+        }
+    }
+
+    fn on_write(&mut self, addr: u16, value: u8) {
+        self.accesses.push((addr, Access::Write));
+        if let Some(inner) = self.inner.as_mut() {
+            inner.on_write(addr, value);
+        }
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, width: usize, height: usize, collision: bool) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.on_draw(x, y, width, height, collision);
+        }
+    }
+
+    fn on_key_wait(&mut self, x: u8) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.on_key_wait(x);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{Config, Platform, StackPolicy};
+    use crate::profiler::Profiler;
+
+    // 200: LD V3, 0
+    // 202: ADD V3, 1
+    // 204: CALL 20A
+    // 206: JP 202
+    // 20A: LD I, 300
+    // 20C: LD [I], V0
+    // 20E: RET
+    fn program() -> Chip8 {
+        let mut c8 = Chip8::new();
+        let rom = [0x63, 0x00, 0x73, 0x01, 0x22, 0x0A, 0x12, 0x02, 0x00, 0x00, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xEE];
+        c8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        c8
+    }
+
+    fn run_until_stop(debugger: &mut Debugger, c8: &mut Chip8) -> Stop {
+        for _ in 0..100 {
+            if let Some(stop) = debugger.run_frame(c8, 10).unwrap() {
+                return stop;
+            }
+        }
+        panic!("the debugger never stopped");
+    }
+
+    #[test]
+    fn condition_should_parse_and_evaluate() {
+        let condition: Condition = "V3 == 0x10".parse().unwrap();
+        assert_eq!(condition, Condition { lhs: Operand::V(3), comparison: Comparison::Eq, rhs: Operand::Value(0x10) });
+
+        let mut c8 = Chip8::new();
+        c8.v[3] = 0x10;
+        assert!(condition.holds(&c8));
+        assert_eq!(condition.to_string(), "V3 == 0x10");
+        c8.memory[0x300] = 7;
+        assert!("[0x300] >= 7".parse::<Condition>().unwrap().holds(&c8));
+        assert!(!"pc < #200".parse::<Condition>().unwrap().holds(&c8));
+        // Bare numbers are hex, like addresses
+        assert_eq!("V3 == 10".parse::<Condition>().unwrap(), condition);
+        assert!("[300] == 7".parse::<Condition>().unwrap().holds(&c8));
+        assert!("V3 ~ 1".parse::<Condition>().is_err());
+        assert!("VG == 1".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn run_frame_should_stop_at_breakpoints_and_resume_past_them() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.run_frame(&mut c8, 10).unwrap(), None);
+        assert_eq!(c8.pc, 0x200);
+
+        let id = debugger.add_breakpoint(Breakpoint { addr: Some(0x204), condition: None });
+        debugger.resume(&c8, Resume::Continue);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Breakpoint { id, pc: 0x204 });
+        assert_eq!(c8.v[3], 1);
+
+        debugger.resume(&c8, Resume::Continue);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Breakpoint { id, pc: 0x204 });
+        assert_eq!(c8.v[3], 2);
+    }
+
+    #[test]
+    fn conditional_breakpoints_should_wait_for_the_condition() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+        let condition = Some("V3 == A".parse().unwrap());
+        let id = debugger.add_breakpoint(Breakpoint { addr: None, condition });
+
+        debugger.resume(&c8, Resume::Continue);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Breakpoint { id, pc: 0x204 });
+        assert_eq!(c8.v[3], 10);
+    }
+
+    #[test]
+    fn watchpoints_should_report_the_accessing_instruction() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+        let id = debugger.add_watchpoint(Watchpoint { addr: 0x300, len: 1, read: false, write: true });
+
+        debugger.resume(&c8, Resume::Continue);
+        let stop = run_until_stop(&mut debugger, &mut c8);
+        assert_eq!(stop, Stop::Watchpoint { id, pc: 0x20C, addr: 0x300, access: Access::Write });
+        assert_eq!(c8.pc, 0x20E);
+
+        assert!(debugger.remove(id));
+        assert!(!debugger.remove(id));
+    }
+
+    #[test]
+    fn watchpoints_should_follow_the_accesses_execution_makes() {
+        // 200: LD I, 300
+        // 202: LD [I], V1        writes 300-301 and leaves I at 302
+        // 204: LD V0, [I]        reads 302 and leaves I at 303
+        // 206: SAVE V0 - V1      writes 303-304
+        // 208: PLANE 3
+        // 20A: DRW V0, V0, 2     reads 303-306, one sprite per plane
+        // 20C: JP 20C
+        let rom = [0xA3, 0x00, 0xF1, 0x55, 0xF0, 0x65, 0x50, 0x12, 0xF3, 0x01, 0xD0, 0x02, 0x12, 0x0C];
+        let mut c8 = Chip8::with_platform(Platform::XoChip);
+        c8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        let mut debugger = Debugger::new();
+        let id = debugger.add_watchpoint(Watchpoint { addr: 0x302, len: 2, read: true, write: true });
+        let read_id = debugger.add_watchpoint(Watchpoint { addr: 0x306, len: 1, read: true, write: false });
+
+        let expected = [
+            Stop::Watchpoint { id, pc: 0x204, addr: 0x302, access: Access::Read },
+            Stop::Watchpoint { id, pc: 0x206, addr: 0x303, access: Access::Write },
+            Stop::Watchpoint { id, pc: 0x20A, addr: 0x303, access: Access::Read },
+        ];
+        for stop in expected {
+            debugger.resume(&c8, Resume::Continue);
+            assert_eq!(run_until_stop(&mut debugger, &mut c8), stop);
+        }
+
+        assert!(debugger.remove(id));
+        c8.pc = 0x20A;
+        debugger.resume(&c8, Resume::Continue);
+        let stop = Stop::Watchpoint { id: read_id, pc: 0x20A, addr: 0x306, access: Access::Read };
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), stop);
+    }
+
+    #[test]
+    fn watchpoints_should_leave_the_attached_observer_in_place() {
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut c8 = program();
+        c8.set_observer(Some(Box::new(profiler.clone())));
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint { addr: 0x300, len: 1, read: false, write: true });
+
+        debugger.resume(&c8, Resume::Continue);
+        run_until_stop(&mut debugger, &mut c8);
+        assert_eq!(profiler.borrow().report().total, 5);
+
+        c8.step_instruction().unwrap();
+        assert_eq!(profiler.borrow().report().total, 6);
+    }
+
+    #[test]
+    fn watchpoints_should_reuse_one_recorder_until_the_last_is_removed() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+        let id = debugger.add_watchpoint(Watchpoint { addr: 0x300, len: 1, read: false, write: true });
+
+        debugger.resume(&c8, Resume::Continue);
+        run_until_stop(&mut debugger, &mut c8);
+        let recorder = debugger.recorder.clone().unwrap();
+        debugger.resume(&c8, Resume::Continue);
+        run_until_stop(&mut debugger, &mut c8);
+        assert!(Rc::ptr_eq(&recorder, debugger.recorder.as_ref().unwrap()));
+        assert!(recorder.borrow().accesses.len() <= 1);
+
+        debugger.remove(id);
+        debugger.resume(&c8, Resume::Step);
+        run_until_stop(&mut debugger, &mut c8);
+        assert!(debugger.recorder.is_none());
+        // Only the test's handle is left once the machine drops the recorder
+        assert_eq!(Rc::strong_count(&recorder), 1);
+    }
+
+    #[test]
+    fn step_over_should_run_the_whole_call() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+
+        for pc in [0x202, 0x204] {
+            debugger.resume(&c8, Resume::Step);
+            assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Step { pc });
+        }
+
+        debugger.resume(&c8, Resume::StepOver);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Step { pc: 0x206 });
+        assert_eq!(c8.sp, 0);
+    }
+
+    #[test]
+    fn step_out_should_stop_after_the_return() {
+        let mut c8 = program();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint { addr: Some(0x20A), condition: None });
+
+        debugger.resume(&c8, Resume::Continue);
+        run_until_stop(&mut debugger, &mut c8);
+        assert_eq!(c8.sp, 1);
+
+        debugger.resume(&c8, Resume::StepOut);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::Step { pc: 0x206 });
+    }
+
+    #[test]
+    fn halting_stack_faults_should_stop_the_debugger() {
+        let config = Config { stack_depth: 1, stack_policy: StackPolicy::Halt, ..Config::default() };
+        let mut c8 = Chip8::with_config(config);
+        // 200: CALL 202; 202: CALL 202
+        c8.memory[0x200..0x204].copy_from_slice(&[0x22, 0x02, 0x22, 0x02]);
+        let mut debugger = Debugger::new();
+
+        debugger.resume(&c8, Resume::Continue);
+        assert_eq!(run_until_stop(&mut debugger, &mut c8), Stop::StackOverflow { pc: 0x202 });
+        assert_eq!(debugger.run_frame(&mut c8, 10).unwrap(), None);
+    }
+
+    #[test]
+    fn format_disassembly_should_mark_pc() {
+        let mut c8 = program();
+        c8.pc = 0x204;
+
+        let listing = Debugger::format_disassembly(&c8, 1, 1);
+        assert_eq!(listing, "   202: 7301  ADD V3, #01\n=> 204: 220A  CALL #20A\n   206: 1202  JP #202");
+    }
+}
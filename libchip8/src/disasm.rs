@@ -0,0 +1,329 @@
+use crate::instruction::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// Bytes per DB line for data that no ANNN points at
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// Why a label was generated, from the strongest reason to the weakest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    /// Target of a CALL.
+    Subroutine,
+    /// Target of a JP or JP V0.
+    Jump,
+    /// Address loaded into I, usually sprite data.
+    Data,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    /// An instruction reached by following the control flow. `operand` holds the address
+    /// word that follows F000.
+    Code { instruction: Instruction, operand: Option<u16> },
+    /// Bytes no traced path executes. `sprite` marks bytes that follow an ANNN target.
+    Data { bytes: Vec<u8>, sprite: bool },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub label: Option<String>,
+    pub item: Item,
+}
+
+/// A ROM split into code and data. Its `Display` is an annotated listing in the same
+/// syntax `Instruction::mnemonic` produces.
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    origin: u16,
+    lines: Vec<Line>,
+    labels: BTreeMap<u16, (LabelKind, String)>,
+}
+
+impl Disassembly {
+    pub fn origin(&self) -> u16 {
+        self.origin
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// The label generated for `addr`, if anything branches to or loads it.
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(|(_, name)| name.as_str())
+    }
+
+    pub fn label_kind(&self, addr: u16) -> Option<LabelKind> {
+        self.labels.get(&addr).map(|(kind, _)| *kind)
+    }
+
+    fn format_addr(&self, addr: u16) -> String {
+        match self.label(addr) {
+            Some(name) => name.to_string(),
+            None => format!("#{:03X}", addr),
+        }
+    }
+}
+
+/// Disassembles `rom` loaded at `origin`, following every jump, call and skip from the
+/// origin to tell code from data. Addresses referenced by JP, CALL and ANNN are labelled.
+pub fn disassemble(rom: &[u8], origin: u16) -> Disassembly {
+    let end = origin as usize + rom.len();
+    let word = |addr: usize| -> Option<u16> {
+        let offset = addr.checked_sub(origin as usize)?;
+        let bytes = rom.get(offset..offset + 2)?;
+        Some((bytes[0] as u16) << 8 | bytes[1] as u16)
+    };
+
+    let mut code: BTreeMap<usize, (Instruction, Option<u16>)> = BTreeMap::new();
+    let mut covered = vec![false; rom.len()];
+    let mut targets: BTreeMap<u16, LabelKind> = BTreeMap::new();
+    let mut target = |addr: u16, kind: LabelKind| {
+        let entry = targets.entry(addr).or_insert(kind);
+        *entry = (*entry).min(kind);
+    };
+
+    let mut pending = vec![origin as usize];
+    while let Some(mut pc) = pending.pop() {
+        loop {
+            if code.contains_key(&pc) {
+                break;
+            }
+            let Some(instruction) = word(pc).and_then(Instruction::decode) else {
+                break;
+            };
+            let size = instruction.size() as usize;
+            let offset = pc - origin as usize;
+            if pc + size > end || covered[offset..offset + size].iter().any(|&c| c) {
+                break;
+            }
+
+            let operand = match instruction {
+                Instruction::LdILong => word(pc + 2),
+                _ => None,
+            };
+            covered[offset..offset + size].fill(true);
+            code.insert(pc, (instruction, operand));
+
+            match instruction {
+                Instruction::Jp(addr) => {
+                    target(addr, LabelKind::Jump);
+                    pending.push(addr as usize);
+                    break;
+                }
+                // The target depends on V0 at run time, so NNN is only the base of a table
+                Instruction::JpV0(_) => break,
+                Instruction::Call(addr) => {
+                    target(addr, LabelKind::Subroutine);
+                    pending.push(addr as usize);
+                }
+                Instruction::Ret | Instruction::Exit => break,
+                Instruction::SeVxByte { .. }
+                | Instruction::SneVxByte { .. }
+                | Instruction::SeVxVy { .. }
+                | Instruction::SneVxVy { .. }
+                | Instruction::SkpVx { .. }
+                | Instruction::SknpVx { .. } => {
+                    // The skipped instruction may be F000 NNNN, which is skipped as a whole
+                    let next = pc + size;
+                    let skipped = if word(next) == Some(0xF000) { 4 } else { 2 };
+                    pending.push(next + skipped);
+                }
+                Instruction::LdI(addr) => target(addr, LabelKind::Data),
+                Instruction::LdILong => {
+                    if let Some(addr) = operand {
+                        target(addr, LabelKind::Data);
+                    }
+                }
+                _ => {}
+            }
+            pc += size;
+        }
+    }
+
+    // Split the rom into lines. Data lines end at the next label so every label starts a line
+    let mut lines = Vec::new();
+    let mut sprite = false;
+    let mut addr = origin as usize;
+    while addr < end {
+        let kind = targets.get(&(addr as u16)).copied();
+        if kind.is_some() {
+            sprite = kind == Some(LabelKind::Data);
+        }
+
+        if let Some(&(instruction, operand)) = code.get(&addr) {
+            lines.push(Line { addr: addr as u16, label: None, item: Item::Code { instruction, operand } });
+            addr += instruction.size() as usize;
+            sprite = false;
+            continue;
+        }
+
+        let max_len = if sprite { 1 } else { DATA_BYTES_PER_LINE };
+        let mut len = 1;
+        while len < max_len
+            && addr + len < end
+            && !code.contains_key(&(addr + len))
+            && !targets.contains_key(&((addr + len) as u16))
+        {
+            len += 1;
+        }
+
+        let offset = addr - origin as usize;
+        let bytes = rom[offset..offset + len].to_vec();
+        lines.push(Line { addr: addr as u16, label: None, item: Item::Data { bytes, sprite } });
+        addr += len;
+    }
+
+    // Targets inside an instruction or outside the rom stay numeric
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.addr).collect();
+    let labels: BTreeMap<u16, (LabelKind, String)> = targets
+        .into_iter()
+        .filter(|(addr, _)| starts.contains(addr))
+        .map(|(addr, kind)| {
+            let prefix = match kind {
+                LabelKind::Subroutine => "sub",
+                LabelKind::Jump => "loc",
+                LabelKind::Data => "data",
+            };
+            (addr, (kind, format!("{}_{:03X}", prefix, addr)))
+        })
+        .collect();
+
+    for line in lines.iter_mut() {
+        line.label = labels.get(&line.addr).map(|(_, name)| name.clone());
+    }
+
+    Disassembly { origin, lines, labels }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.origin != 0x200 {
+            writeln!(f, "    ORG #{:03X}", self.origin)?;
+        }
+
+        for line in &self.lines {
+            if let Some(label) = &line.label {
+                writeln!(f, "{}:", label)?;
+            }
+
+            match &line.item {
+                Item::Code { instruction, operand } => {
+                    let mut text = instruction.mnemonic(|addr| self.format_addr(addr));
+                    let mut raw = format!("{:04X}", instruction.encode());
+                    if let Some(operand) = operand {
+                        match self.label(*operand) {
+                            Some(name) => text = format!("{} {}", text, name),
+                            None => text = format!("{} #{:04X}", text, operand),
+                        }
+                        raw = format!("{} {:04X}", raw, operand);
+                    }
+                    writeln!(f, "    {:<27} ; {:03X}: {}", text, line.addr, raw)?;
+                }
+                Item::Data { bytes, sprite } => {
+                    let text = bytes.iter().map(|b| format!("#{:02X}", b)).collect::<Vec<_>>().join(", ");
+                    let text = format!("DB {}", text);
+                    if *sprite {
+                        let pixels: String =
+                            (0..8).map(|bit| if bytes[0] & (0x80 >> bit) != 0 { '#' } else { '.' }).collect();
+                        writeln!(f, "    {:<27} ; {:03X}: {}", text, line.addr, pixels)?;
+                    } else {
+                        writeln!(f, "    {:<27} ; {:03X}", text, line.addr)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: CALL 208
+    // 202: LD I, 20E
+    // 204: DRW V0, V1, 2
+    // 206: JP 206
+    // 208: SE V0, 1
+    // 20A: ADD V0, 1
+    // 20C: RET
+    // 20E: sprite data
+    const ROM: [u8; 20] = [
+        0x22, 0x08, 0xA2, 0x0E, 0xD0, 0x12, 0x12, 0x06, 0x30, 0x01, 0x70, 0x01, 0x00, 0xEE, 0xF0, 0x90, 0x00,
+        0xE0, 0x12, 0x34,
+    ];
+
+    #[test]
+    fn disassemble_should_separate_code_from_data() {
+        let disassembly = disassemble(&ROM, 0x200);
+        let code: Vec<u16> = disassembly
+            .lines()
+            .iter()
+            .filter(|line| matches!(line.item, Item::Code { .. }))
+            .map(|line| line.addr)
+            .collect();
+
+        assert_eq!(code, vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C]);
+    }
+
+    #[test]
+    fn disassemble_should_label_branch_targets_and_sprites() {
+        let disassembly = disassemble(&ROM, 0x200);
+
+        assert_eq!(disassembly.label(0x208), Some("sub_208"));
+        assert_eq!(disassembly.label(0x206), Some("loc_206"));
+        assert_eq!(disassembly.label(0x20E), Some("data_20E"));
+        assert_eq!(disassembly.label_kind(0x20E), Some(LabelKind::Data));
+        assert_eq!(disassembly.label(0x200), None);
+    }
+
+    #[test]
+    fn display_should_print_an_annotated_listing() {
+        let listing = disassemble(&ROM, 0x200).to_string();
+
+        assert!(listing.contains("CALL sub_208"));
+        assert!(listing.contains("LD I, data_20E"));
+        assert!(listing.contains("sub_208:\n    SE V0, #01"));
+        assert!(listing.contains("DB #F0                      ; 20E: ####...."));
+        assert!(listing.contains("DB #90                      ; 20F: #..#...."));
+        assert!(!listing.contains("CLS"));
+    }
+
+    #[test]
+    fn disassemble_should_keep_the_long_load_operand() {
+        // LD I, LONG 0x204; JP 0x200; data
+        let rom = [0xF0, 0x00, 0x02, 0x06, 0x12, 0x00, 0xAA];
+        let listing = disassemble(&rom, 0x200).to_string();
+
+        assert!(listing.contains("LD I, LONG data_206"));
+        assert!(listing.contains("F000 0206"));
+    }
+
+    #[test]
+    fn disassemble_should_not_follow_computed_jumps() {
+        // JP V0, 0x202; CLS, which only the computed jump could reach
+        let rom = [0xB2, 0x02, 0x00, 0xE0];
+        let disassembly = disassemble(&rom, 0x200);
+
+        let code: Vec<u16> = disassembly
+            .lines()
+            .iter()
+            .filter(|line| matches!(line.item, Item::Code { .. }))
+            .map(|line| line.addr)
+            .collect();
+
+        assert_eq!(code, vec![0x200]);
+        assert_eq!(disassembly.label(0x202), None);
+    }
+
+    #[test]
+    fn disassemble_should_leave_targets_outside_the_rom_numeric() {
+        let listing = disassemble(&[0x13, 0x00, 1, 2, 3, 4, 5, 6, 7, 8, 9], 0x200).to_string();
+        assert!(listing.contains("JP #300"));
+        assert!(listing.contains("DB #01, #02, #03, #04, #05, #06, #07, #08 ; 202\n    DB #09"));
+    }
+}
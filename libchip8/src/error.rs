@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    Io(io::Error),
+    RomTooLarge { size: usize, max: usize },
+    UnknownOpcode { pc: u16, opcode: u16 },
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { addr: usize },
+    InvalidKey { key: u8 },
+    InvalidRegister { x: usize },
+    InvalidFont { size: usize },
+    InvalidState(&'static str),
+    InvalidConfig(&'static str),
+    UnsupportedStateVersion { version: u16 },
+    StateRomMismatch { expected: u64, found: u64 },
+    Asm { file: String, line: usize, message: String },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::Io(e) => write!(f, "io error: {}", e),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(f, "rom is {} bytes but only {} bytes are available", size, max)
+            }
+            Chip8Error::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:#06X} at {:#05X}", opcode, pc)
+            }
+            Chip8Error::StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            Chip8Error::StackUnderflow { pc } => write!(f, "stack underflow at {:#05X}", pc),
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:#06X}", addr)
+            }
+            Chip8Error::InvalidKey { key } => write!(f, "invalid key {:#04X}", key),
+            Chip8Error::InvalidRegister { x } => write!(f, "there is no register V{:X}", x),
+            Chip8Error::InvalidFont { size } => {
+                write!(f, "font is {} bytes, expected 80 for the small font or 240 with the big font", size)
+            }
+            Chip8Error::InvalidState(reason) => write!(f, "invalid save state: {}", reason),
+            Chip8Error::InvalidConfig(reason) => write!(f, "invalid configuration: {}", reason),
+            Chip8Error::UnsupportedStateVersion { version } => {
+                write!(f, "save state version {} is not supported", version)
+            }
+            Chip8Error::StateRomMismatch { expected, found } => write!(
+                f,
+                "save state belongs to rom {:016X} but rom {:016X} is loaded",
+                found, expected
+            ),
+            Chip8Error::Asm { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl Error for Chip8Error {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Chip8Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Chip8Error {
+    fn from(e: io::Error) -> Self {
+        Chip8Error::Io(e)
+    }
+}
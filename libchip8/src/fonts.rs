@@ -1,5 +1,11 @@
-pub const BYTES_PER_LINE: u8 = 5;
-pub const FONTS: [u8; 80] =
+use crate::error::Chip8Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+pub(crate) const BYTES_PER_LINE: u8 = 5;
+pub(crate) const FONTS: [u8; 80] =
     [
         0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
         0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -18,3 +24,272 @@ pub const FONTS: [u8; 80] =
         0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
         0xF0, 0x80, 0xF0, 0x80, 0x80  // F
     ];
+
+pub(crate) const BIG_BYTES_PER_LINE: u8 = 10;
+pub(crate) const BIG_FONTS: [u8; 160] =
+    [
+        0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+        0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+        0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+        0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+        0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+        0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+    ];
+
+// The 4x5 fonts below are the ones collected by Octo from each interpreter's rom
+
+const VIP_FONTS: [u8; 80] =
+    [
+        0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+        0x60, 0x20, 0x20, 0x20, 0x70, // 1
+        0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+        0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+        0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+        0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+        0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+        0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+        0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+        0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+        0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+        0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+        0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+        0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+        0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+        0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+    ];
+
+const DREAM_6800_FONTS: [u8; 80] =
+    [
+        0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+        0x40, 0x40, 0x40, 0x40, 0x40, // 1
+        0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+        0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+        0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+        0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+        0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+        0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+        0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+        0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+        0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+        0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+        0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+        0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80  // F
+    ];
+
+const ETI_660_FONTS: [u8; 80] =
+    [
+        0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+        0x20, 0x20, 0x20, 0x20, 0x20, // 1
+        0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+        0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+        0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+        0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+        0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+        0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+        0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+        0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+        0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+        0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+        0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+        0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80  // F
+    ];
+
+const FISH_N_CHIPS_FONTS: [u8; 80] =
+    [
+        0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+        0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+        0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+        0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+        0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+        0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+        0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+        0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+        0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+        0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+        0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+        0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+        0x60, 0x80, 0x80, 0x80, 0x60, // C
+        0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+        0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+        0xE0, 0x80, 0xC0, 0x80, 0x80  // F
+    ];
+
+// SUPER-CHIP 1.1 only had big digits, the letters are the ones of BIG_FONTS
+const SCHIP_BIG_FONTS: [u8; 160] =
+    [
+        0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+        0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+        0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+        0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+        0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+        0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+        0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+        0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+        0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+        0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+        0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+        0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+        0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0  // F
+    ];
+
+/// The hex digit sprites loaded into memory: the 4x5 font FX29 points at and, for
+/// SUPER-CHIP and later, the 8x10 font FX30 points at, which is stored right after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Font {
+    pub small: [u8; 80],
+    pub big: Option<[u8; 160]>,
+}
+
+impl Font {
+    /// Reads a font from raw sprite data: 80 bytes of 4x5 digits, optionally followed
+    /// by 160 bytes of 8x10 digits.
+    pub fn from_bytes(data: &[u8]) -> Result<Font, Chip8Error> {
+        let (small, big) = match data.len() {
+            80 => (data, None),
+            240 => (&data[..80], Some(&data[80..])),
+            size => return Err(Chip8Error::InvalidFont { size }),
+        };
+        Ok(Font {
+            small: small.try_into().unwrap(),
+            big: big.map(|big| big.try_into().unwrap()),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Font, Chip8Error> {
+        Font::from_bytes(&fs::read(path)?)
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        FontSet::Octo.font()
+    }
+}
+
+/// The fonts of well known interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FontSet {
+    /// The COSMAC VIP interpreter's font.
+    Vip,
+    /// The font of CHIP-8 for the DREAM 6800.
+    Dream6800,
+    /// The font of CHIP-8 for the ETI-660.
+    Eti660,
+    /// The font of FISH 'N' CHIPS for the HP-48.
+    FishNChips,
+    /// The CHIP-48 font, which SUPER-CHIP and most modern interpreters kept.
+    Chip48,
+    /// The CHIP-48 font with SUPER-CHIP 1.1's big digits.
+    SuperChip,
+    /// The CHIP-48 font with the big font of the Octo IDE.
+    Octo,
+}
+
+impl FontSet {
+    pub const ALL: [FontSet; 7] = [
+        FontSet::Vip,
+        FontSet::Dream6800,
+        FontSet::Eti660,
+        FontSet::FishNChips,
+        FontSet::Chip48,
+        FontSet::SuperChip,
+        FontSet::Octo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FontSet::Vip => "vip",
+            FontSet::Dream6800 => "dream6800",
+            FontSet::Eti660 => "eti660",
+            FontSet::FishNChips => "fishnchips",
+            FontSet::Chip48 => "chip48",
+            FontSet::SuperChip => "schip",
+            FontSet::Octo => "octo",
+        }
+    }
+
+    pub fn font(self) -> Font {
+        let (small, big) = match self {
+            FontSet::Vip => (VIP_FONTS, None),
+            FontSet::Dream6800 => (DREAM_6800_FONTS, None),
+            FontSet::Eti660 => (ETI_660_FONTS, None),
+            FontSet::FishNChips => (FISH_N_CHIPS_FONTS, None),
+            FontSet::Chip48 => (FONTS, None),
+            FontSet::SuperChip => (FONTS, Some(SCHIP_BIG_FONTS)),
+            FontSet::Octo => (FONTS, Some(BIG_FONTS)),
+        };
+        Font { small, big }
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FontSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_ascii_lowercase().replace(['-', '_', '.', ' ', '\''], "");
+        let font_set = match normalized.as_str() {
+            "vip" | "cosmacvip" => FontSet::Vip,
+            "dream6800" | "dream" => FontSet::Dream6800,
+            "eti660" | "eti" => FontSet::Eti660,
+            "fishnchips" | "fish" => FontSet::FishNChips,
+            "chip48" => FontSet::Chip48,
+            "schip" | "superchip" | "schip11" => FontSet::SuperChip,
+            "octo" | "xochip" => FontSet::Octo,
+            _ => {
+                let names: Vec<&str> = FontSet::ALL.iter().map(|f| f.name()).collect();
+                return Err(format!("unknown font '{}', expected one of: {}", s, names.join(", ")));
+            }
+        };
+        Ok(font_set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_font_set_should_round_trip_through_its_name() {
+        for font_set in FontSet::ALL {
+            assert_eq!(font_set.name().parse::<FontSet>(), Ok(font_set));
+        }
+        assert_eq!("FISH 'N' CHIPS".parse::<FontSet>(), Ok(FontSet::FishNChips));
+        assert_eq!("DREAM-6800".parse::<FontSet>(), Ok(FontSet::Dream6800));
+        assert!("comic sans".parse::<FontSet>().is_err());
+    }
+
+    #[test]
+    fn from_bytes_should_accept_small_and_big_fonts() {
+        let font = FontSet::SuperChip.font();
+        let mut data = font.small.to_vec();
+        assert_eq!(Font::from_bytes(&data).unwrap(), Font { big: None, ..font });
+
+        data.extend_from_slice(&font.big.unwrap());
+        assert_eq!(Font::from_bytes(&data).unwrap(), font);
+
+        assert!(matches!(Font::from_bytes(&data[..100]), Err(Chip8Error::InvalidFont { size: 100 })));
+    }
+}
@@ -0,0 +1,639 @@
+use crate::chip8::Chip8;
+use crate::debugger::{Access, Breakpoint, Debugger, Resume, Stop, Watchpoint};
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+// Register numbers in `g`, `p` and `P` packets, and in TARGET_XML
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const REG_COUNT: usize = 21;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+const TARGET_XML: &str = concat!(
+    r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd"><target version="1.0">"#,
+    r#"<feature name="org.chip8.core">"#,
+    r#"<reg name="v0" bitsize="8" type="uint8" regnum="0"/><reg name="v1" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="v2" bitsize="8" type="uint8"/><reg name="v3" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="v4" bitsize="8" type="uint8"/><reg name="v5" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="v6" bitsize="8" type="uint8"/><reg name="v7" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="v8" bitsize="8" type="uint8"/><reg name="v9" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="va" bitsize="8" type="uint8"/><reg name="vb" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="vc" bitsize="8" type="uint8"/><reg name="vd" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="ve" bitsize="8" type="uint8"/><reg name="vf" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="i" bitsize="16" type="uint16"/><reg name="pc" bitsize="16" type="code_ptr"/>"#,
+    r#"<reg name="sp" bitsize="16" type="uint16"/><reg name="dt" bitsize="8" type="uint8"/>"#,
+    r#"<reg name="st" bitsize="8" type="uint8"/>"#,
+    r#"</feature></target>"#
+);
+
+struct Connection {
+    stream: TcpStream,
+    input: Vec<u8>,
+    no_ack: bool,
+}
+
+/// A GDB remote serial protocol stub over a local TCP port. Registers are `v0`-`vf`, `i`,
+/// `pc`, `sp`, `dt` and `st`, and memory is the machine's address space. Like `Debugger` it
+/// runs a frame at a time, so the frontend keeps drawing while GDB is attached.
+///
+/// The rom stays paused until a client attaches and continues it. Detaching lets it run.
+pub struct GdbServer {
+    listener: TcpListener,
+    connection: Option<Connection>,
+    debugger: Debugger,
+    // Ids of the debugger's points, keyed by the Z packet type, address and kind
+    points: HashMap<(u8, u16, u16), usize>,
+    last_signal: u8,
+    // Set between a `c` or `s` packet and the stop reply it is waiting for
+    running: bool,
+    // Why the last connection was dropped, until the frontend takes it
+    error: Option<io::Error>,
+}
+
+impl GdbServer {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<GdbServer> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(GdbServer {
+            listener,
+            connection: None,
+            debugger: Debugger::new(),
+            points: HashMap::new(),
+            last_signal: SIGTRAP,
+            running: false,
+            error: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Returns the error that dropped the last connection, once. A client detaching or
+    /// closing the connection is not an error.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Accepts a client, answers its packets, then runs a frame unless the rom is paused.
+    /// Returns true if the rom ran. Faults stop the rom and are reported to GDB as SIGILL.
+    pub fn run_frame(&mut self, c8: &mut Chip8, cycles_per_frame: u32) -> bool {
+        if self.connection.is_none() {
+            self.accept();
+        }
+        if let Err(e) = self.poll(c8) {
+            if e.kind() != ErrorKind::UnexpectedEof {
+                self.error = Some(e);
+            }
+            self.disconnect(c8);
+        }
+        if self.debugger.is_paused() {
+            return false;
+        }
+
+        let signal = match self.debugger.run_frame(c8, cycles_per_frame) {
+            Ok(None) => return true,
+            Ok(Some(Stop::Halted { .. })) => {
+                self.running = false;
+                self.send("W00");
+                return true;
+            }
+            Ok(Some(stop)) => Some(stop),
+            Err(_) => None,
+        };
+        self.stopped(signal);
+        true
+    }
+
+    fn accept(&mut self) {
+        let Ok((stream, _)) = self.listener.accept() else {
+            return;
+        };
+        if stream.set_nonblocking(true).is_err() {
+            return;
+        }
+        let _ = stream.set_nodelay(true);
+        self.connection = Some(Connection { stream, input: Vec::new(), no_ack: false });
+        self.debugger.pause();
+        self.last_signal = SIGTRAP;
+    }
+
+    // Detaching or dropping the connection clears GDB's breakpoints and lets the rom run
+    fn disconnect(&mut self, c8: &Chip8) {
+        self.connection = None;
+        self.debugger.clear();
+        self.points.clear();
+        self.running = false;
+        if !c8.halted {
+            self.debugger.resume(c8, Resume::Continue);
+        }
+    }
+
+    fn poll(&mut self, c8: &mut Chip8) -> io::Result<()> {
+        let Some(connection) = self.connection.as_mut() else {
+            return Ok(());
+        };
+
+        let mut buf = [0; 1024];
+        loop {
+            match connection.stream.read(&mut buf) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => connection.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        while let Some(packet) = self.next_packet()? {
+            match packet {
+                Packet::Interrupt => {
+                    if self.running {
+                        self.debugger.pause();
+                        self.stop_with(SIGINT);
+                    }
+                }
+                Packet::Command(command) => {
+                    if let Some(reply) = self.handle(c8, &command) {
+                        self.send(&reply);
+                    }
+                    if self.connection.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+        let Some(connection) = self.connection.as_mut() else {
+            return Ok(None);
+        };
+        let input = &mut connection.input;
+
+        loop {
+            match input.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    input.remove(0);
+                    return Ok(Some(Packet::Interrupt));
+                }
+                Some(b'$') => break,
+                // Acks and line noise between packets
+                Some(_) => {
+                    input.remove(0);
+                }
+            }
+        }
+
+        let Some(end) = input.iter().position(|&b| b == b'#') else {
+            return Ok(None);
+        };
+        if input.len() < end + 3 {
+            return Ok(None);
+        }
+
+        let body = input[1..end].to_vec();
+        let checksum = std::str::from_utf8(&input[end + 1..end + 3]).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+        input.drain(..end + 3);
+
+        let valid = checksum == Some(body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+        if !connection.no_ack {
+            connection.stream.set_nonblocking(false)?;
+            connection.stream.write_all(if valid { b"+" } else { b"-" })?;
+            connection.stream.set_nonblocking(true)?;
+        }
+        if !valid {
+            return self.next_packet();
+        }
+        Ok(Some(Packet::Command(String::from_utf8_lossy(&body).into_owned())))
+    }
+
+    fn send(&mut self, reply: &str) {
+        let Some(connection) = self.connection.as_mut() else {
+            return;
+        };
+        let checksum = reply.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", reply, checksum);
+
+        let result = connection
+            .stream
+            .set_nonblocking(false)
+            .and_then(|_| connection.stream.write_all(packet.as_bytes()))
+            .and_then(|_| connection.stream.set_nonblocking(true));
+        if let Err(e) = result {
+            self.error = Some(e);
+            self.connection = None;
+        }
+    }
+
+    fn stopped(&mut self, stop: Option<Stop>) {
+        self.running = false;
+        let reply = match stop {
+            Some(Stop::Breakpoint { .. }) => format!("T{:02x}swbreak:;", SIGTRAP),
+            Some(Stop::Watchpoint { id, addr, access, .. }) => {
+                // GDB only recognises an access watchpoint by the kind it was set with
+                let access_watch = self.points.iter().any(|(&(z, ..), &point)| point == id && z == 4);
+                let name = match access {
+                    _ if access_watch => "awatch",
+                    Access::Read => "rwatch",
+                    Access::Write => "watch",
+                };
+                format!("T{:02x}{}:{:x};", SIGTRAP, name, addr)
+            }
+            // A stack fault is the closest thing to a segfault the machine has
+            Some(Stop::StackOverflow { .. } | Stop::StackUnderflow { .. }) => return self.stop_with(SIGSEGV),
+            Some(_) => return self.stop_with(SIGTRAP),
+            None => return self.stop_with(SIGILL),
+        };
+        self.last_signal = SIGTRAP;
+        self.send(&reply);
+    }
+
+    fn stop_with(&mut self, signal: u8) {
+        self.running = false;
+        self.last_signal = signal;
+        self.send(&format!("S{:02x}", signal));
+    }
+
+    // Returns the reply, or None while a `c` or `s` waits for the rom to stop
+    fn handle(&mut self, c8: &mut Chip8, command: &str) -> Option<String> {
+        let (kind, args) = command.split_at(command.chars().next().map_or(0, char::len_utf8));
+        let reply = match kind {
+            "?" => format!("S{:02x}", self.last_signal),
+            "g" => (0..REG_COUNT).map(|n| read_register(c8, n)).collect(),
+            "G" => ok_or_error(write_registers(c8, args)),
+            "p" => match usize::from_str_radix(args, 16).ok().filter(|n| *n < REG_COUNT) {
+                Some(n) => read_register(c8, n),
+                None => "E01".to_string(),
+            },
+            "P" => ok_or_error(args.split_once('=').and_then(|(n, value)| {
+                let n = usize::from_str_radix(n, 16).ok()?;
+                write_register(c8, n, &decode_hex(value)?)
+            })),
+            "m" => read_memory(c8, args).unwrap_or_else(|| "E01".to_string()),
+            "M" => ok_or_error(write_memory(c8, args)),
+            "c" | "s" => {
+                if c8.halted {
+                    return Some("W00".to_string());
+                }
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    c8.pc = addr;
+                }
+                let resume = if kind == "c" { Resume::Continue } else { Resume::Step };
+                self.debugger.resume(c8, resume);
+                self.running = true;
+                return None;
+            }
+            "Z" | "z" => ok_or_error(self.toggle_point(kind == "Z", args)),
+            "H" | "T" => "OK".to_string(),
+            "D" => {
+                self.send("OK");
+                self.disconnect(c8);
+                return None;
+            }
+            "k" => {
+                self.disconnect(c8);
+                return None;
+            }
+            "q" | "Q" => self.handle_query(command),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn handle_query(&mut self, command: &str) -> String {
+        if command.starts_with("qSupported") {
+            return "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+".to_string();
+        }
+        if command == "QStartNoAckMode" {
+            self.send("OK");
+            if let Some(connection) = self.connection.as_mut() {
+                connection.no_ack = true;
+            }
+            return String::new();
+        }
+        if let Some(range) = command.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, len)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Ok(offset), Ok(len)) = (usize::from_str_radix(offset, 16), usize::from_str_radix(len, 16)) else {
+                return "E01".to_string();
+            };
+            let start = offset.min(TARGET_XML.len());
+            let end = (start + len).min(TARGET_XML.len());
+            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &TARGET_XML[start..end]);
+        }
+        match command {
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    // Z0 and Z1 are breakpoints, Z2, Z3 and Z4 write, read and access watchpoints
+    fn toggle_point(&mut self, insert: bool, args: &str) -> Option<()> {
+        let mut fields = args.split(',');
+        let z = fields.next()?.parse::<u8>().ok()?;
+        let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let kind = u16::from_str_radix(fields.next()?.split(';').next()?, 16).ok()?;
+        let key = (z, addr, kind);
+
+        if !insert {
+            let id = self.points.remove(&key)?;
+            return self.debugger.remove(id).then_some(());
+        }
+        if self.points.contains_key(&key) {
+            return Some(());
+        }
+        let id = match z {
+            0 | 1 => self.debugger.add_breakpoint(Breakpoint { addr: Some(addr), condition: None }),
+            2..=4 => {
+                let (read, write) = (z != 2, z != 3);
+                self.debugger.add_watchpoint(Watchpoint { addr, len: kind.max(1), read, write })
+            }
+            _ => return None,
+        };
+        self.points.insert(key, id);
+        Some(())
+    }
+}
+
+enum Packet {
+    Interrupt,
+    Command(String),
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    match result {
+        Some(()) => "OK".to_string(),
+        None => "E01".to_string(),
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// 16 bit registers are sent little endian, as GDB expects from a little endian target
+fn register_size(n: usize) -> usize {
+    match n {
+        REG_I | REG_PC | REG_SP => 2,
+        _ => 1,
+    }
+}
+
+fn read_register(c8: &Chip8, n: usize) -> String {
+    let value = match n {
+        0..=15 => c8.v[n] as u16,
+        REG_I => c8.i,
+        REG_PC => c8.pc,
+        REG_SP => c8.sp,
+        REG_DT => c8.delay_timer as u16,
+        _ => c8.sound_timer as u16,
+    };
+    encode_hex(&value.to_le_bytes()[..register_size(n)])
+}
+
+fn write_register(c8: &mut Chip8, n: usize, bytes: &[u8]) -> Option<()> {
+    if bytes.len() != register_size(n) {
+        return None;
+    }
+    let value = u16::from_le_bytes([bytes[0], bytes.get(1).copied().unwrap_or(0)]);
+    match n {
+        0..=15 => c8.v[n] = value as u8,
+        REG_I => c8.i = value,
+        REG_PC => c8.pc = value,
+        REG_SP if value as usize > c8.stack.len() => return None,
+        REG_SP => c8.sp = value,
+        REG_DT => c8.delay_timer = value as u8,
+        REG_ST => c8.sound_timer = value as u8,
+        _ => return None,
+    }
+    Some(())
+}
+
+fn write_registers(c8: &mut Chip8, hex: &str) -> Option<()> {
+    let bytes = decode_hex(hex)?;
+    let mut offset = 0;
+    for n in 0..REG_COUNT {
+        let size = register_size(n);
+        write_register(c8, n, bytes.get(offset..offset + size)?)?;
+        offset += size;
+    }
+    Some(())
+}
+
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((usize::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+// Reads past the end of memory are cut short, as GDB allows
+fn read_memory(c8: &Chip8, args: &str) -> Option<String> {
+    let (addr, len) = parse_range(args)?;
+    let end = addr.saturating_add(len).min(c8.memory.len());
+    if addr >= end && len > 0 {
+        return None;
+    }
+    Some(encode_hex(&c8.memory[addr.min(end)..end]))
+}
+
+fn write_memory(c8: &mut Chip8, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = parse_range(range)?;
+    let bytes = decode_hex(data)?;
+    if bytes.len() != len || addr + len > c8.memory.len() {
+        return None;
+    }
+    c8.memory[addr..addr + len].copy_from_slice(&bytes);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Client {
+        stream: TcpStream,
+        server: GdbServer,
+        c8: Chip8,
+    }
+
+    impl Client {
+        // 200: LD V3, 5
+        // 202: ADD V3, 1
+        // 204: LD I, 300
+        // 206: LD [I], V3
+        // 208: JP 202
+        fn connect() -> Client {
+            let mut c8 = Chip8::new();
+            let rom = [0x63, 0x05, 0x73, 0x01, 0xA3, 0x00, 0xF3, 0x55, 0x12, 0x02];
+            c8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+
+            let mut server = GdbServer::bind("127.0.0.1:0").unwrap();
+            let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+            stream.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+            while !server.is_connected() {
+                server.run_frame(&mut c8, 10);
+            }
+            Client { stream, server, c8 }
+        }
+
+        fn send_raw(&mut self, bytes: &[u8]) {
+            self.stream.write_all(bytes).unwrap();
+        }
+
+        fn send(&mut self, packet: &str) {
+            let checksum = packet.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+            self.send_raw(format!("${}#{:02x}", packet, checksum).as_bytes());
+        }
+
+        // Runs frames until a whole reply arrives, skipping acks
+        fn reply(&mut self) -> String {
+            let mut received = Vec::new();
+            for _ in 0..1000 {
+                self.server.run_frame(&mut self.c8, 10);
+                let mut buf = [0; 4096];
+                if let Ok(n) = self.stream.read(&mut buf) {
+                    received.extend_from_slice(&buf[..n]);
+                }
+                let text = String::from_utf8_lossy(&received).trim_start_matches('+').to_string();
+                if let Some(end) = text.find('#').filter(|end| text.len() >= end + 3) {
+                    return text[1..end].to_string();
+                }
+            }
+            panic!("no reply, received {:?}", String::from_utf8_lossy(&received));
+        }
+
+        fn exchange(&mut self, packet: &str) -> String {
+            self.send(packet);
+            self.reply()
+        }
+    }
+
+    #[test]
+    fn server_should_read_and_write_registers() {
+        let mut client = Client::connect();
+        client.c8.v[0xA] = 0x42;
+
+        let registers = client.exchange("g");
+        assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 2 + 1 + 1));
+        assert_eq!(&registers[20..22], "42");
+        assert_eq!(&registers[36..40], "0002");
+
+        assert_eq!(client.exchange("P11=0402"), "OK");
+        assert_eq!(client.c8.pc, 0x204);
+        assert_eq!(client.exchange("p11"), "0402");
+        assert_eq!(client.exchange("P12=ff00"), "E01");
+    }
+
+    #[test]
+    fn server_should_read_and_write_memory() {
+        let mut client = Client::connect();
+
+        assert_eq!(client.exchange("m200,4"), "63057301");
+        assert_eq!(client.exchange("M300,2:beef"), "OK");
+        assert_eq!(client.c8.memory[0x300..0x302], [0xBE, 0xEF]);
+        assert_eq!(client.exchange("mffe,8"), "0000");
+        assert_eq!(client.exchange("M1000,1:00"), "E01");
+    }
+
+    #[test]
+    fn server_should_step_and_stop_at_breakpoints() {
+        let mut client = Client::connect();
+
+        assert_eq!(client.exchange("s"), "S05");
+        assert_eq!(client.c8.pc, 0x202);
+
+        assert_eq!(client.exchange("Z0,206,2"), "OK");
+        assert_eq!(client.exchange("c"), "T05swbreak:;");
+        assert_eq!(client.c8.pc, 0x206);
+        assert_eq!(client.c8.v[3], 6);
+
+        assert_eq!(client.exchange("c"), "T05swbreak:;");
+        assert_eq!(client.c8.v[3], 7);
+
+        assert_eq!(client.exchange("z0,206,2"), "OK");
+        assert_eq!(client.exchange("z0,206,2"), "E01");
+    }
+
+    #[test]
+    fn server_should_report_watchpoints() {
+        let mut client = Client::connect();
+
+        assert_eq!(client.exchange("Z2,300,1"), "OK");
+        assert_eq!(client.exchange("c"), "T05watch:300;");
+        assert_eq!(client.c8.pc, 0x208);
+        assert_eq!(client.c8.memory[0x303], 6);
+    }
+
+    #[test]
+    fn server_should_stop_on_interrupt() {
+        let mut client = Client::connect();
+
+        client.send("c");
+        client.send_raw(&[0x03]);
+        assert_eq!(client.reply(), "S02");
+        assert_eq!(client.exchange("?"), "S02");
+    }
+
+    #[test]
+    fn closing_the_connection_should_not_be_an_error() {
+        let Client { stream, mut server, mut c8 } = Client::connect();
+        drop(stream);
+
+        for _ in 0..1000 {
+            if !server.is_connected() {
+                break;
+            }
+            server.run_frame(&mut c8, 10);
+        }
+        assert!(!server.is_connected());
+        assert!(server.take_error().is_none());
+    }
+
+    #[test]
+    fn server_should_reject_bad_checksums_and_serve_the_target_description() {
+        let mut client = Client::connect();
+
+        client.send_raw(b"$g#00");
+        let mut nak = [0; 1];
+        for _ in 0..100 {
+            client.server.run_frame(&mut client.c8, 10);
+            if client.stream.read(&mut nak).is_ok() {
+                break;
+            }
+        }
+        assert_eq!(&nak, b"-");
+
+        let xml = client.exchange("qXfer:features:read:target.xml:0,1000");
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains(r#"name="pc""#));
+        assert_eq!(client.exchange("vMustReplyEmpty"), "");
+    }
+}
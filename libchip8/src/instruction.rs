@@ -0,0 +1,374 @@
+use std::fmt;
+
+/// A decoded opcode. Decoding knows every instruction of every supported instruction set,
+/// whether the machine executing it supports that instruction is decided by `Chip8`.
+///
+/// Register operands are register numbers 0x0-0xF, `nn` is an 8 bit immediate, `n` a
+/// 4 bit immediate and addresses are 12 bits wide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN, calls a machine code routine on the original hardware.
+    Sys(u16),
+    /// 00CN, scrolls the display down by N lines.
+    ScrollDown { n: u8 },
+    /// 00DN, scrolls the display up by N lines.
+    ScrollUp { n: u8 },
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00FB, scrolls the display right by 4 pixels.
+    ScrollRight,
+    /// 00FC, scrolls the display left by 4 pixels.
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE, switches to the 64x32 resolution.
+    Low,
+    /// 00FF, switches to the 128x64 resolution.
+    High,
+    /// 1NNN
+    Jp(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SeVxByte { x: u8, nn: u8 },
+    /// 4XNN
+    SneVxByte { x: u8, nn: u8 },
+    /// 5XY0
+    SeVxVy { x: u8, y: u8 },
+    /// 5XY2, stores VX through VY at I.
+    SaveVxVy { x: u8, y: u8 },
+    /// 5XY3, loads VX through VY from I.
+    LoadVxVy { x: u8, y: u8 },
+    /// 6XNN
+    LdVxByte { x: u8, nn: u8 },
+    /// 7XNN
+    AddVxByte { x: u8, nn: u8 },
+    /// 8XY0
+    LdVxVy { x: u8, y: u8 },
+    /// 8XY1
+    OrVxVy { x: u8, y: u8 },
+    /// 8XY2
+    AndVxVy { x: u8, y: u8 },
+    /// 8XY3
+    XorVxVy { x: u8, y: u8 },
+    /// 8XY4
+    AddVxVy { x: u8, y: u8 },
+    /// 8XY5
+    SubVxVy { x: u8, y: u8 },
+    /// 8XY6
+    ShrVxVy { x: u8, y: u8 },
+    /// 8XY7
+    SubnVxVy { x: u8, y: u8 },
+    /// 8XYE
+    ShlVxVy { x: u8, y: u8 },
+    /// 9XY0
+    SneVxVy { x: u8, y: u8 },
+    /// ANNN
+    LdI(u16),
+    /// BNNN, jumps to NNN plus V0, or plus VX with the jump_uses_vx quirk.
+    JpV0(u16),
+    /// CXNN
+    RndVxByte { x: u8, nn: u8 },
+    /// DXYN
+    Drw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkpVx { x: u8 },
+    /// EXA1
+    SknpVx { x: u8 },
+    /// F000 NNNN, loads the 16 bit address in the following word into I.
+    LdILong,
+    /// FN01, selects the bitplanes that drawing, clearing and scrolling affect.
+    Plane(u8),
+    /// F002, loads the 16 byte audio pattern from I.
+    Audio,
+    /// FX07
+    LdVxDt { x: u8 },
+    /// FX0A
+    LdVxK { x: u8 },
+    /// FX15
+    LdDtVx { x: u8 },
+    /// FX18
+    LdStVx { x: u8 },
+    /// FX1E
+    AddIVx { x: u8 },
+    /// FX29
+    LdFVx { x: u8 },
+    /// FX30
+    LdHfVx { x: u8 },
+    /// FX33
+    LdBVx { x: u8 },
+    /// FX3A
+    PitchVx { x: u8 },
+    /// FX55
+    LdIVx { x: u8 },
+    /// FX65
+    LdVxI { x: u8 },
+    /// FX75
+    LdRVx { x: u8 },
+    /// FX85
+    LdVxR { x: u8 },
+}
+
+impl Instruction {
+    /// Decodes an opcode, returning None when no instruction set defines it.
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        let instruction = match (opcode & 0xF000) >> 12 {
+            0x0 => match opcode {
+                0x00C0..=0x00CF => Instruction::ScrollDown { n },
+                0x00D0..=0x00DF => Instruction::ScrollUp { n },
+                0x00E0 => Instruction::Cls,
+                0x00EE => Instruction::Ret,
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::Low,
+                0x00FF => Instruction::High,
+                _ => Instruction::Sys(nnn),
+            },
+            0x1 => Instruction::Jp(nnn),
+            0x2 => Instruction::Call(nnn),
+            0x3 => Instruction::SeVxByte { x, nn },
+            0x4 => Instruction::SneVxByte { x, nn },
+            0x5 => match n {
+                0x0 => Instruction::SeVxVy { x, y },
+                0x2 => Instruction::SaveVxVy { x, y },
+                0x3 => Instruction::LoadVxVy { x, y },
+                _ => return None,
+            },
+            0x6 => Instruction::LdVxByte { x, nn },
+            0x7 => Instruction::AddVxByte { x, nn },
+            0x8 => match n {
+                0x0 => Instruction::LdVxVy { x, y },
+                0x1 => Instruction::OrVxVy { x, y },
+                0x2 => Instruction::AndVxVy { x, y },
+                0x3 => Instruction::XorVxVy { x, y },
+                0x4 => Instruction::AddVxVy { x, y },
+                0x5 => Instruction::SubVxVy { x, y },
+                0x6 => Instruction::ShrVxVy { x, y },
+                0x7 => Instruction::SubnVxVy { x, y },
+                0xE => Instruction::ShlVxVy { x, y },
+                _ => return None,
+            },
+            0x9 if n == 0 => Instruction::SneVxVy { x, y },
+            0xA => Instruction::LdI(nnn),
+            0xB => Instruction::JpV0(nnn),
+            0xC => Instruction::RndVxByte { x, nn },
+            0xD => Instruction::Drw { x, y, n },
+            0xE => match nn {
+                0x9E => Instruction::SkpVx { x },
+                0xA1 => Instruction::SknpVx { x },
+                _ => return None,
+            },
+            0xF => match (x, nn) {
+                (0x0, 0x00) => Instruction::LdILong,
+                (_, 0x01) => Instruction::Plane(x),
+                (0x0, 0x02) => Instruction::Audio,
+                (_, 0x07) => Instruction::LdVxDt { x },
+                (_, 0x0A) => Instruction::LdVxK { x },
+                (_, 0x15) => Instruction::LdDtVx { x },
+                (_, 0x18) => Instruction::LdStVx { x },
+                (_, 0x1E) => Instruction::AddIVx { x },
+                (_, 0x29) => Instruction::LdFVx { x },
+                (_, 0x30) => Instruction::LdHfVx { x },
+                (_, 0x33) => Instruction::LdBVx { x },
+                (_, 0x3A) => Instruction::PitchVx { x },
+                (_, 0x55) => Instruction::LdIVx { x },
+                (_, 0x65) => Instruction::LdVxI { x },
+                (_, 0x75) => Instruction::LdRVx { x },
+                (_, 0x85) => Instruction::LdVxR { x },
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// The opcode for this instruction. Out of range operands are truncated to their field width.
+    pub fn encode(&self) -> u16 {
+        let xy = |op: u16, x: u8, y: u8, n: u16| op | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | n;
+        let xnn = |op: u16, x: u8, nn: u8| op | ((x as u16 & 0xF) << 8) | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | ((x as u16 & 0xF) << 8) | nn;
+
+        match *self {
+            Instruction::Sys(addr) => addr & 0x0FFF,
+            Instruction::ScrollDown { n } => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp { n } => 0x00D0 | (n as u16 & 0xF),
+            Instruction::Cls => 0x00E0,
+            Instruction::Ret => 0x00EE,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::Low => 0x00FE,
+            Instruction::High => 0x00FF,
+            Instruction::Jp(addr) => 0x1000 | (addr & 0x0FFF),
+            Instruction::Call(addr) => 0x2000 | (addr & 0x0FFF),
+            Instruction::SeVxByte { x, nn } => xnn(0x3000, x, nn),
+            Instruction::SneVxByte { x, nn } => xnn(0x4000, x, nn),
+            Instruction::SeVxVy { x, y } => xy(0x5000, x, y, 0x0),
+            Instruction::SaveVxVy { x, y } => xy(0x5000, x, y, 0x2),
+            Instruction::LoadVxVy { x, y } => xy(0x5000, x, y, 0x3),
+            Instruction::LdVxByte { x, nn } => xnn(0x6000, x, nn),
+            Instruction::AddVxByte { x, nn } => xnn(0x7000, x, nn),
+            Instruction::LdVxVy { x, y } => xy(0x8000, x, y, 0x0),
+            Instruction::OrVxVy { x, y } => xy(0x8000, x, y, 0x1),
+            Instruction::AndVxVy { x, y } => xy(0x8000, x, y, 0x2),
+            Instruction::XorVxVy { x, y } => xy(0x8000, x, y, 0x3),
+            Instruction::AddVxVy { x, y } => xy(0x8000, x, y, 0x4),
+            Instruction::SubVxVy { x, y } => xy(0x8000, x, y, 0x5),
+            Instruction::ShrVxVy { x, y } => xy(0x8000, x, y, 0x6),
+            Instruction::SubnVxVy { x, y } => xy(0x8000, x, y, 0x7),
+            Instruction::ShlVxVy { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::SneVxVy { x, y } => xy(0x9000, x, y, 0x0),
+            Instruction::LdI(addr) => 0xA000 | (addr & 0x0FFF),
+            Instruction::JpV0(addr) => 0xB000 | (addr & 0x0FFF),
+            Instruction::RndVxByte { x, nn } => xnn(0xC000, x, nn),
+            Instruction::Drw { x, y, n } => xy(0xD000, x, y, n as u16 & 0xF),
+            Instruction::SkpVx { x } => xnn(0xE000, x, 0x9E),
+            Instruction::SknpVx { x } => xnn(0xE000, x, 0xA1),
+            Instruction::LdILong => 0xF000,
+            Instruction::Plane(mask) => fx(mask, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::LdVxDt { x } => fx(x, 0x07),
+            Instruction::LdVxK { x } => fx(x, 0x0A),
+            Instruction::LdDtVx { x } => fx(x, 0x15),
+            Instruction::LdStVx { x } => fx(x, 0x18),
+            Instruction::AddIVx { x } => fx(x, 0x1E),
+            Instruction::LdFVx { x } => fx(x, 0x29),
+            Instruction::LdHfVx { x } => fx(x, 0x30),
+            Instruction::LdBVx { x } => fx(x, 0x33),
+            Instruction::PitchVx { x } => fx(x, 0x3A),
+            Instruction::LdIVx { x } => fx(x, 0x55),
+            Instruction::LdVxI { x } => fx(x, 0x65),
+            Instruction::LdRVx { x } => fx(x, 0x75),
+            Instruction::LdVxR { x } => fx(x, 0x85),
+        }
+    }
+
+    /// The Cowgod style assembly for this instruction, with `addr` formatting address operands.
+    /// F000's operand lives in the following word, so it is written as `LD I, LONG` and the
+    /// caller appends the address.
+    pub fn mnemonic(&self, addr: impl Fn(u16) -> String) -> String {
+        match *self {
+            Instruction::Sys(a) => format!("SYS {}", addr(a)),
+            Instruction::ScrollDown { n } => format!("SCD {}", n),
+            Instruction::ScrollUp { n } => format!("SCU {}", n),
+            Instruction::Cls => "CLS".to_string(),
+            Instruction::Ret => "RET".to_string(),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::Low => "LOW".to_string(),
+            Instruction::High => "HIGH".to_string(),
+            Instruction::Jp(a) => format!("JP {}", addr(a)),
+            Instruction::Call(a) => format!("CALL {}", addr(a)),
+            Instruction::SeVxByte { x, nn } => format!("SE V{:X}, #{:02X}", x, nn),
+            Instruction::SneVxByte { x, nn } => format!("SNE V{:X}, #{:02X}", x, nn),
+            Instruction::SeVxVy { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::SaveVxVy { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadVxVy { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            Instruction::LdVxByte { x, nn } => format!("LD V{:X}, #{:02X}", x, nn),
+            Instruction::AddVxByte { x, nn } => format!("ADD V{:X}, #{:02X}", x, nn),
+            Instruction::LdVxVy { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::OrVxVy { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::AndVxVy { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::XorVxVy { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::AddVxVy { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::SubVxVy { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::ShrVxVy { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::SubnVxVy { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShlVxVy { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::SneVxVy { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::LdI(a) => format!("LD I, {}", addr(a)),
+            Instruction::JpV0(a) => format!("JP V0, {}", addr(a)),
+            Instruction::RndVxByte { x, nn } => format!("RND V{:X}, #{:02X}", x, nn),
+            Instruction::Drw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkpVx { x } => format!("SKP V{:X}", x),
+            Instruction::SknpVx { x } => format!("SKNP V{:X}", x),
+            Instruction::LdILong => "LD I, LONG".to_string(),
+            Instruction::Plane(mask) => format!("PLANE {}", mask),
+            Instruction::Audio => "AUDIO".to_string(),
+            Instruction::LdVxDt { x } => format!("LD V{:X}, DT", x),
+            Instruction::LdVxK { x } => format!("LD V{:X}, K", x),
+            Instruction::LdDtVx { x } => format!("LD DT, V{:X}", x),
+            Instruction::LdStVx { x } => format!("LD ST, V{:X}", x),
+            Instruction::AddIVx { x } => format!("ADD I, V{:X}", x),
+            Instruction::LdFVx { x } => format!("LD F, V{:X}", x),
+            Instruction::LdHfVx { x } => format!("LD HF, V{:X}", x),
+            Instruction::LdBVx { x } => format!("LD B, V{:X}", x),
+            Instruction::PitchVx { x } => format!("PITCH V{:X}", x),
+            Instruction::LdIVx { x } => format!("LD [I], V{:X}", x),
+            Instruction::LdVxI { x } => format!("LD V{:X}, [I]", x),
+            Instruction::LdRVx { x } => format!("LD R, V{:X}", x),
+            Instruction::LdVxR { x } => format!("LD V{:X}, R", x),
+        }
+    }
+
+    /// Length in bytes, F000 NNNN is the only instruction that is four bytes long.
+    pub fn size(&self) -> u16 {
+        match self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic(|addr| format!("#{:03X}", addr)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_should_split_operands() {
+        assert_eq!(Instruction::decode(0x00E0), Some(Instruction::Cls));
+        assert_eq!(Instruction::decode(0x12A4), Some(Instruction::Jp(0x2A4)));
+        assert_eq!(Instruction::decode(0x3A42), Some(Instruction::SeVxByte { x: 0xA, nn: 0x42 }));
+        assert_eq!(Instruction::decode(0x8BC6), Some(Instruction::ShrVxVy { x: 0xB, y: 0xC }));
+        assert_eq!(Instruction::decode(0xD125), Some(Instruction::Drw { x: 1, y: 2, n: 5 }));
+        assert_eq!(Instruction::decode(0xF301), Some(Instruction::Plane(3)));
+        assert_eq!(Instruction::decode(0x0123), Some(Instruction::Sys(0x123)));
+    }
+
+    #[test]
+    fn decode_should_reject_undefined_opcodes() {
+        for opcode in [0x5121, 0x8008, 0x9001, 0xE000, 0xF100, 0xF102, 0xF0FF] {
+            assert_eq!(Instruction::decode(opcode), None, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn encode_should_round_trip_every_defined_opcode() {
+        for opcode in 0..=0xFFFF {
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode, "{:?}", instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn display_should_use_cowgod_mnemonics() {
+        assert_eq!(Instruction::Jp(0x2A4).to_string(), "JP #2A4");
+        assert_eq!(Instruction::Drw { x: 1, y: 0xA, n: 5 }.to_string(), "DRW V1, VA, 5");
+        assert_eq!(Instruction::LdIVx { x: 3 }.to_string(), "LD [I], V3");
+        assert_eq!(Instruction::Call(0x300).mnemonic(|_| "draw".to_string()), "CALL draw");
+    }
+
+    #[test]
+    fn size_should_count_the_long_load_operand() {
+        assert_eq!(Instruction::LdILong.size(), 4);
+        assert_eq!(Instruction::Cls.size(), 2);
+    }
+}
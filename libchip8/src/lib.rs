@@ -1,6 +1,39 @@
 extern crate core;
 
+mod analysis;
+mod asm;
 mod chip8;
+mod debugger;
+mod disasm;
+mod error;
 mod fonts;
+mod gdb;
+mod instruction;
+mod observer;
+mod octo;
+mod platform;
+mod profiler;
+mod quirks;
+mod rewind;
+mod rng;
+mod state;
+mod trace;
 
-pub use crate::chip8::*;
\ No newline at end of file
+pub use crate::analysis::*;
+pub use crate::asm::*;
+pub use crate::chip8::*;
+pub use crate::debugger::*;
+pub use crate::disasm::*;
+pub use crate::error::*;
+pub use crate::fonts::*;
+pub use crate::gdb::*;
+pub use crate::instruction::*;
+pub use crate::observer::*;
+pub use crate::octo::*;
+pub use crate::platform::*;
+pub use crate::profiler::*;
+pub use crate::quirks::*;
+pub use crate::rewind::*;
+pub use crate::rng::*;
+pub use crate::state::*;
+pub use crate::trace::*;
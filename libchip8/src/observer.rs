@@ -0,0 +1,135 @@
+use crate::instruction::Instruction;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Hooks `Chip8` calls while it executes, for cheats, heatmaps and the like. Every method
+/// does nothing by default. Reads and writes are the data accesses opcodes make, such as
+/// DXYN's sprite, FX33's digits and FX55/FX65's registers, not instruction fetches.
+///
+/// Attach one with `Chip8::set_observer`. A machine without one skips all of this.
+pub trait Observer {
+    /// Called before the instruction at `pc` executes.
+    fn on_execute(&mut self, _pc: u16, _instruction: Instruction) {}
+
+    fn on_read(&mut self, _addr: u16, _value: u8) {}
+
+    /// Called after `value` was written to `addr`.
+    fn on_write(&mut self, _addr: u16, _value: u8) {}
+
+    /// Called after DXYN drew a sprite of `width` by `height` pixels at `x`, `y`, which are
+    /// VX and VY wrapped onto the screen.
+    fn on_draw(&mut self, _x: u8, _y: u8, _width: usize, _height: usize, _collision: bool) {}
+
+    /// Called every cycle FX0A spends waiting for a key to store in VX.
+    fn on_key_wait(&mut self, _x: u8) {}
+}
+
+/// Lets the caller keep a handle on an observer it gave to `Chip8` and read its results.
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn on_execute(&mut self, pc: u16, instruction: Instruction) {
+        self.borrow_mut().on_execute(pc, instruction)
+    }
+
+    fn on_read(&mut self, addr: u16, value: u8) {
+        self.borrow_mut().on_read(addr, value)
+    }
+
+    fn on_write(&mut self, addr: u16, value: u8) {
+        self.borrow_mut().on_write(addr, value)
+    }
+
+    fn on_draw(&mut self, x: u8, y: u8, width: usize, height: usize, collision: bool) {
+        self.borrow_mut().on_draw(x, y, width, height, collision)
+    }
+
+    fn on_key_wait(&mut self, x: u8) {
+        self.borrow_mut().on_key_wait(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[derive(Default)]
+    struct Recorder {
+        executed: Vec<u16>,
+        reads: Vec<(u16, u8)>,
+        writes: Vec<(u16, u8)>,
+        draws: Vec<(u8, u8, usize, usize, bool)>,
+        key_waits: usize,
+    }
+
+    impl Observer for Recorder {
+        fn on_execute(&mut self, pc: u16, _instruction: Instruction) {
+            self.executed.push(pc);
+        }
+
+        fn on_read(&mut self, addr: u16, value: u8) {
+            self.reads.push((addr, value));
+        }
+
+        fn on_write(&mut self, addr: u16, value: u8) {
+            self.writes.push((addr, value));
+        }
+
+        fn on_draw(&mut self, x: u8, y: u8, width: usize, height: usize, collision: bool) {
+            self.draws.push((x, y, width, height, collision));
+        }
+
+        fn on_key_wait(&mut self, _x: u8) {
+            self.key_waits += 1;
+        }
+    }
+
+    fn run(rom: &[u8], steps: usize) -> (Chip8, Rc<RefCell<Recorder>>) {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let mut c8 = Chip8::new();
+        c8.write_memory(0x200, rom).unwrap();
+        c8.set_observer(Some(Box::new(recorder.clone())));
+        for _ in 0..steps {
+            c8.step_instruction().unwrap();
+        }
+        (c8, recorder)
+    }
+
+    #[test]
+    fn observer_should_see_bcd_and_register_stores() {
+        // LD V0, 123; LD I, 300; LD B, V0; LD V1, 7; LD [I], V1
+        let (_, recorder) = run(&[0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33, 0x61, 0x07, 0xF1, 0x55], 5);
+        let recorder = recorder.borrow();
+
+        assert_eq!(recorder.executed, vec![0x200, 0x202, 0x204, 0x206, 0x208]);
+        assert_eq!(recorder.writes, vec![(0x300, 1), (0x301, 2), (0x302, 3), (0x300, 123), (0x301, 7)]);
+        assert!(recorder.reads.is_empty());
+    }
+
+    #[test]
+    fn observer_should_see_register_loads() {
+        // LD I, 206; LD V1, [I]; data
+        let (_, recorder) = run(&[0xA2, 0x06, 0xF1, 0x65, 0x00, 0x00, 0xAB, 0xCD], 2);
+        assert_eq!(recorder.borrow().reads, vec![(0x206, 0xAB), (0x207, 0xCD)]);
+    }
+
+    #[test]
+    fn observer_should_see_sprite_reads_and_draws() {
+        // LD I, 208; DRW V0, V0, 1; DRW V0, V0, 1; sprite
+        let (_, recorder) = run(&[0xA2, 0x08, 0xD0, 0x01, 0xD0, 0x01, 0x00, 0x00, 0x80], 3);
+        let recorder = recorder.borrow();
+
+        assert_eq!(recorder.reads, vec![(0x208, 0x80), (0x208, 0x80)]);
+        assert_eq!(recorder.draws, vec![(0, 0, 8, 1, false), (0, 0, 8, 1, true)]);
+    }
+
+    #[test]
+    fn observer_should_see_key_waits() {
+        // LD V2, K
+        let (mut c8, recorder) = run(&[0xF2, 0x0A], 3);
+        assert_eq!(recorder.borrow().key_waits, 3);
+
+        c8.set_observer(None);
+        c8.step_instruction().unwrap();
+        assert_eq!(recorder.borrow().key_waits, 3);
+    }
+}
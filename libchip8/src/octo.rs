@@ -0,0 +1,972 @@
+use crate::error::Chip8Error;
+use crate::platform::DEFAULT_START_ADDRESS;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+// Stops a macro that expands to itself from running forever
+const MAX_MACRO_EXPANSIONS: usize = 100_000;
+
+const KEYWORDS: [&str; 40] = [
+    ":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=", "<<=", "==", "!=", "<", ">", "<=", ">=", "key", "-key", ";",
+    "return", "clear", "bcd", "save", "load", "sprite", "jump", "jump0", "native", "delay", "buzzer", "random",
+    "hex", "bighex", "long", "loop", "again", "while", "if", "then", "begin", "else",
+];
+
+/// Compiles Octo source into a rom loaded at 0x200.
+///
+/// Supports labels, `:const`, `:alias`, `:calc`, `:macro`, `:org`, `:byte`, `:pointer`,
+/// `:unpack` and `:next`, the CHIP-8, SUPER-CHIP and XO-CHIP statements, `if ... then`,
+/// `if ... begin ... else ... end`, `loop ... while ... again` and bare numbers as data.
+/// Like Octo, execution starts at the `main` label, with a jump to it at 0x200 unless
+/// `: main` is the first thing in the program.
+pub fn compile_octo(source: &str) -> Result<Vec<u8>, Chip8Error> {
+    Compiler::new("<input>", source).compile()
+}
+
+pub fn compile_octo_file(path: impl AsRef<Path>) -> Result<Vec<u8>, Chip8Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    Compiler::new(&path.display().to_string(), &source).compile()
+}
+
+struct Token {
+    text: String,
+    line: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+struct Loop {
+    start: usize,
+    // Jumps out of the loop emitted by `while`, patched by `again`
+    exits: Vec<usize>,
+}
+
+#[derive(Clone, Copy)]
+enum Fixup {
+    // The low 12 bits of an opcode
+    Addr,
+    // A big endian 16 bit word
+    Word,
+    // The high byte of an address, below `nibble << 4` for `:unpack N label`
+    HighByte(Option<u8>),
+    LowByte,
+}
+
+enum Operand {
+    Register(u8),
+    Immediate(u8),
+}
+
+struct Condition {
+    x: u8,
+    op: String,
+    rhs: Option<Operand>,
+}
+
+struct Compiler {
+    file: String,
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(usize, String, Fixup, usize)>,
+    loops: Vec<Loop>,
+    branches: Vec<usize>,
+    next_label: Option<String>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn new(file: &str, source: &str) -> Compiler {
+        let mut tokens = VecDeque::new();
+        for (index, line) in source.lines().enumerate() {
+            for word in line.split_whitespace() {
+                if word.starts_with('#') {
+                    break;
+                }
+                tokens.push_back(Token { text: word.to_string(), line: index + 1 });
+            }
+        }
+
+        Compiler {
+            file: file.to_string(),
+            tokens,
+            line: 1,
+            rom: Vec::new(),
+            here: DEFAULT_START_ADDRESS as usize,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+            next_label: None,
+            expansions: 0,
+        }
+    }
+
+    fn compile(mut self) -> Result<Vec<u8>, Chip8Error> {
+        let starts_with_main = self.peek() == Some(":") && self.tokens.get(1).is_some_and(|t| t.text == "main");
+        if !starts_with_main {
+            self.emit_jump_to("main", 0x1000)?;
+        }
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if !self.loops.is_empty() {
+            return Err(self.error("'loop' without a matching 'again'"));
+        }
+        if !self.branches.is_empty() {
+            return Err(self.error("'begin' without a matching 'end'"));
+        }
+        if !self.labels.contains_key("main") {
+            return Err(Chip8Error::Asm {
+                file: self.file.clone(),
+                line: 1,
+                message: "the program has no 'main' label".to_string(),
+            });
+        }
+
+        for (addr, name, fixup, line) in std::mem::take(&mut self.fixups) {
+            self.line = line;
+            match self.labels.get(&name) {
+                Some(&target) => self.patch(addr, target, fixup)?,
+                None => return Err(self.error(format!("'{}' is not defined", name))),
+            }
+        }
+
+        Ok(self.rom)
+    }
+
+    fn statement(&mut self) -> Result<(), Chip8Error> {
+        let token = self.next()?;
+
+        match token.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                let value = self.calc_block()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = if self.peek() == Some("{") {
+                    self.calc_block()? as i64
+                } else {
+                    self.register()? as i64
+                };
+                if !(0..=0xF).contains(&register) {
+                    return Err(self.error(format!("{} is not a register", register)));
+                }
+                self.aliases.insert(name, register as u8);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let addr = self.value_or_calc()? as i64;
+                if !(DEFAULT_START_ADDRESS as i64..=0xFFFF).contains(&addr) {
+                    return Err(self.error(format!("cannot place code at {:#X}", addr)));
+                }
+                self.here = addr as usize;
+            }
+            ":byte" => {
+                let value = self.value_or_calc()? as i64;
+                let byte = self.check_byte(value)?;
+                self.emit(byte);
+            }
+            ":pointer" => {
+                let here = self.here;
+                let target = self.target()?;
+                self.emit(0);
+                self.emit(0);
+                self.resolve(here, target, Fixup::Word)?;
+            }
+            ":unpack" => {
+                let high = match self.peek() {
+                    Some("long") => {
+                        self.next()?;
+                        None
+                    }
+                    _ => Some(self.immediate(0, 0xF)? as u8),
+                };
+                let target = self.target()?;
+                let here = self.here;
+                self.emit_op(0x6000);
+                self.emit_op(0x6100);
+                self.resolve(here + 1, target.clone(), Fixup::HighByte(high))?;
+                self.resolve(here + 3, target, Fixup::LowByte)?;
+            }
+            ":next" => self.next_label = Some(self.name()?),
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ";" | "return" => self.emit_op(0x00EE),
+            "clear" => self.emit_op(0x00E0),
+            "hires" => self.emit_op(0x00FF),
+            "lores" => self.emit_op(0x00FE),
+            "exit" => self.emit_op(0x00FD),
+            "scroll-right" => self.emit_op(0x00FB),
+            "scroll-left" => self.emit_op(0x00FC),
+            "scroll-down" => {
+                let n = self.immediate(0, 0xF)? as u16;
+                self.emit_op(0x00C0 | n);
+            }
+            "scroll-up" => {
+                let n = self.immediate(0, 0xF)? as u16;
+                self.emit_op(0x00D0 | n);
+            }
+            "plane" => {
+                let n = self.immediate(0, 0x3)? as u16;
+                self.emit_op(0xF001 | n << 8);
+            }
+            "audio" => self.emit_op(0xF002),
+            "bcd" => self.emit_x(0xF033)?,
+            "saveflags" => self.emit_x(0xF075)?,
+            "loadflags" => self.emit_x(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()? as u16;
+                    let n = if token == "save" { 0x2 } else { 0x3 };
+                    self.emit_op(0x5000 | x << 8 | y << 4 | n);
+                } else {
+                    let nn = if token == "save" { 0x55 } else { 0x65 };
+                    self.emit_op(0xF000 | x << 8 | nn);
+                }
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.immediate(0, 0xF)? as u16;
+                self.emit_op(0xD000 | x << 8 | y << 4 | n);
+            }
+            "jump" => self.emit_addr_op(0x1000)?,
+            "jump0" => self.emit_addr_op(0xB000)?,
+            "native" => self.emit_addr_op(0x0000)?,
+            "delay" => {
+                self.expect(":=")?;
+                self.emit_x(0xF015)?;
+            }
+            "buzzer" => {
+                self.expect(":=")?;
+                self.emit_x(0xF018)?;
+            }
+            "pitch" => {
+                self.expect(":=")?;
+                self.emit_x(0xF03A)?;
+            }
+            "i" => self.i_statement()?,
+            "loop" => self.loops.push(Loop { start: self.here, exits: Vec::new() }),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("'while' outside of a loop"));
+                }
+                let condition = self.condition()?;
+                self.emit_condition(&condition, true);
+                let exit = self.here;
+                self.emit_op(0x1000);
+                self.loops.last_mut().unwrap().exits.push(exit);
+            }
+            "again" => {
+                let Some(lp) = self.loops.pop() else {
+                    return Err(self.error("'again' without a matching 'loop'"));
+                };
+                self.emit_op(0x1000 | lp.start as u16);
+                for exit in lp.exits {
+                    self.patch_jump(exit);
+                }
+            }
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()?.as_str() {
+                    "then" => self.emit_condition(&condition, false),
+                    "begin" => {
+                        self.emit_condition(&condition, true);
+                        self.branches.push(self.here);
+                        self.emit_op(0x1000);
+                    }
+                    other => return Err(self.error(format!("expected 'then' or 'begin', got '{}'", other))),
+                }
+            }
+            "else" => {
+                let Some(branch) = self.branches.pop() else {
+                    return Err(self.error("'else' without a matching 'begin'"));
+                };
+                self.branches.push(self.here);
+                self.emit_op(0x1000);
+                self.patch_jump(branch);
+            }
+            "end" => {
+                let Some(branch) = self.branches.pop() else {
+                    return Err(self.error("'end' without a matching 'begin'"));
+                };
+                self.patch_jump(branch);
+            }
+            _ => {
+                if let Some(x) = self.register_named(&token) {
+                    return self.register_statement(x);
+                }
+                if let Some(value) = self.number(&token) {
+                    let byte = self.check_byte(value as i64)?;
+                    self.emit(byte);
+                    return Ok(());
+                }
+                if self.macros.contains_key(&token) {
+                    return self.expand_macro(&token);
+                }
+                if token.starts_with(':') || !is_name(&token) {
+                    return Err(self.error(format!("unexpected '{}'", token)));
+                }
+                self.emit_jump_to(&token, 0x2000)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<(), Chip8Error> {
+        match self.next()?.as_str() {
+            "+=" => self.emit_x(0xF01E),
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    self.emit_x(0xF029)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    self.emit_x(0xF030)
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.emit_op(0xF000);
+                    let here = self.here;
+                    let target = self.target()?;
+                    self.emit(0);
+                    self.emit(0);
+                    self.resolve(here, target, Fixup::Word)
+                }
+                _ => self.emit_addr_op(0xA000),
+            },
+            other => Err(self.error(format!("expected ':=' or '+=' after 'i', got '{}'", other))),
+        }
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), Chip8Error> {
+        let x16 = (x as u16) << 8;
+        let op = self.next()?;
+
+        if op == ":=" {
+            match self.peek() {
+                Some("delay") => {
+                    self.next()?;
+                    self.emit_op(0xF007 | x16);
+                }
+                Some("key") => {
+                    self.next()?;
+                    self.emit_op(0xF00A | x16);
+                }
+                Some("random") => {
+                    self.next()?;
+                    let nn = self.immediate(0, 0xFF)? as u16;
+                    self.emit_op(0xC000 | x16 | nn);
+                }
+                _ => match self.operand()? {
+                    Operand::Register(y) => self.emit_op(0x8000 | x16 | (y as u16) << 4),
+                    Operand::Immediate(nn) => self.emit_op(0x6000 | x16 | nn as u16),
+                },
+            }
+            return Ok(());
+        }
+
+        let n = match op.as_str() {
+            "+=" => 0x4,
+            "-=" => 0x5,
+            "=-" => 0x7,
+            "|=" => 0x1,
+            "&=" => 0x2,
+            "^=" => 0x3,
+            ">>=" => 0x6,
+            "<<=" => 0xE,
+            _ => return Err(self.error(format!("unknown operator '{}'", op))),
+        };
+
+        match (op.as_str(), self.operand()?) {
+            (_, Operand::Register(y)) => self.emit_op(0x8000 | x16 | (y as u16) << 4 | n),
+            ("+=", Operand::Immediate(nn)) => self.emit_op(0x7000 | x16 | nn as u16),
+            ("-=", Operand::Immediate(nn)) => self.emit_op(0x7000 | x16 | nn.wrapping_neg() as u16),
+            _ => return Err(self.error(format!("'{}' needs a register on the right", op))),
+        }
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, Chip8Error> {
+        let x = self.register()?;
+        let op = self.next()?;
+        let rhs = match op.as_str() {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(self.operand()?),
+            _ => return Err(self.error(format!("unknown comparison '{}'", op))),
+        };
+        Ok(Condition { x, op, rhs })
+    }
+
+    // Emits code that skips the next instruction when the condition is false, or when it
+    // is true with `negate`. Ordered comparisons subtract into VF and test the flag.
+    fn emit_condition(&mut self, condition: &Condition, negate: bool) {
+        let x = condition.x as u16;
+        match (condition.op.as_str(), &condition.rhs) {
+            ("key", _) | ("-key", _) => {
+                let skip_unless_pressed = (condition.op == "key") != negate;
+                self.emit_op(if skip_unless_pressed { 0xE0A1 } else { 0xE09E } | x << 8);
+            }
+            ("==", Some(rhs)) | ("!=", Some(rhs)) => {
+                let skip_unless_equal = (condition.op == "==") != negate;
+                let op = match (rhs, skip_unless_equal) {
+                    (Operand::Register(y), true) => 0x9000 | x << 8 | (*y as u16) << 4,
+                    (Operand::Register(y), false) => 0x5000 | x << 8 | (*y as u16) << 4,
+                    (Operand::Immediate(nn), true) => 0x4000 | x << 8 | *nn as u16,
+                    (Operand::Immediate(nn), false) => 0x3000 | x << 8 | *nn as u16,
+                };
+                self.emit_op(op);
+            }
+            (op, Some(rhs)) => {
+                // VF = 1 when a >= b
+                let swap = op == ">" || op == "<=";
+                match (rhs, swap) {
+                    (Operand::Register(y), false) => {
+                        self.emit_op(0x8F00 | x << 4);
+                        self.emit_op(0x8F05 | (*y as u16) << 4);
+                    }
+                    (Operand::Register(y), true) => {
+                        self.emit_op(0x8F00 | (*y as u16) << 4);
+                        self.emit_op(0x8F05 | x << 4);
+                    }
+                    (Operand::Immediate(nn), false) => {
+                        self.emit_op(0x6F00 | *nn as u16);
+                        self.emit_op(0x8F07 | x << 4);
+                    }
+                    (Operand::Immediate(nn), true) => {
+                        self.emit_op(0x6F00 | *nn as u16);
+                        self.emit_op(0x8F05 | x << 4);
+                    }
+                }
+                let true_when_set = op == ">=" || op == "<=";
+                self.emit_op(if true_when_set == negate { 0x4F00 } else { 0x3F00 });
+            }
+            _ => unreachable!("conditions are validated when parsed"),
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), Chip8Error> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.pop_front().ok_or_else(|| self.error("':macro' is missing its closing '}'"))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), Chip8Error> {
+        self.expansions += 1;
+        if self.expansions > MAX_MACRO_EXPANSIONS {
+            return Err(self.error(format!("'{}' expands forever", name)));
+        }
+
+        let count = self.macros[name].params.len();
+        let mut args = Vec::with_capacity(count);
+        for _ in 0..count {
+            args.push(self.next()?);
+        }
+
+        let line = self.line;
+        let mac = &self.macros[name];
+        let expanded: Vec<Token> = mac
+            .body
+            .iter()
+            .map(|token| match mac.params.iter().position(|p| *p == token.text) {
+                Some(index) => Token { text: args[index].clone(), line },
+                None => Token { text: token.text.clone(), line },
+            })
+            .collect();
+
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // `:calc` expressions are evaluated right to left without precedence, as in Octo
+    fn calc_block(&mut self) -> Result<f64, Chip8Error> {
+        self.expect("{")?;
+        let value = self.calc_expr()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expr(&mut self) -> Result<f64, Chip8Error> {
+        let lhs = self.calc_term()?;
+        if matches!(self.peek(), Some("}") | Some(")") | None) {
+            return Ok(lhs);
+        }
+
+        let op = self.next()?;
+        let rhs = self.calc_expr()?;
+        let (a, b) = (lhs as i64, rhs as i64);
+        let value = match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            "%" => lhs % rhs,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => (a << b) as f64,
+            ">>" => (a >> b) as f64,
+            "<" => (lhs < rhs) as u8 as f64,
+            ">" => (lhs > rhs) as u8 as f64,
+            "<=" => (lhs <= rhs) as u8 as f64,
+            ">=" => (lhs >= rhs) as u8 as f64,
+            "==" => (lhs == rhs) as u8 as f64,
+            "!=" => (lhs != rhs) as u8 as f64,
+            _ => return Err(self.error(format!("unknown operator '{}'", op))),
+        };
+        Ok(value)
+    }
+
+    fn calc_term(&mut self) -> Result<f64, Chip8Error> {
+        let token = self.next()?;
+        let unary: Option<fn(f64) -> f64> = match token.as_str() {
+            "(" => {
+                let value = self.calc_expr()?;
+                self.expect(")")?;
+                return Ok(value);
+            }
+            "@" => {
+                let addr = self.calc_term()? as usize;
+                let byte = addr.checked_sub(DEFAULT_START_ADDRESS as usize).and_then(|offset| self.rom.get(offset));
+                return Ok(*byte.unwrap_or(&0) as f64);
+            }
+            "-" => Some(|v| -v),
+            "~" => Some(|v| !(v as i64) as f64),
+            "!" => Some(|v| (v == 0.0) as u8 as f64),
+            "sin" => Some(f64::sin),
+            "cos" => Some(f64::cos),
+            "tan" => Some(f64::tan),
+            "exp" => Some(f64::exp),
+            "log" => Some(f64::ln),
+            "abs" => Some(f64::abs),
+            "sqrt" => Some(f64::sqrt),
+            "sign" => Some(f64::signum),
+            "ceil" => Some(f64::ceil),
+            "floor" => Some(f64::floor),
+            _ => None,
+        };
+        if let Some(unary) = unary {
+            return Ok(unary(self.calc_term()?));
+        }
+
+        match token.as_str() {
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => {
+                if let Some(value) = token.parse::<f64>().ok().or_else(|| self.number(&token)) {
+                    return Ok(value);
+                }
+                if let Some(register) = self.register_named(&token) {
+                    return Ok(register as f64);
+                }
+                Err(self.error(format!("'{}' is not defined", token)))
+            }
+        }
+    }
+
+    fn emit(&mut self, byte: u8) {
+        let offset = self.here - DEFAULT_START_ADDRESS as usize;
+        if self.rom.len() <= offset {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+    }
+
+    fn emit_op(&mut self, opcode: u16) {
+        if let Some(name) = self.next_label.take() {
+            self.labels.insert(name, self.here + 1);
+        }
+        let [high, low] = opcode.to_be_bytes();
+        self.emit(high);
+        self.emit(low);
+    }
+
+    fn emit_x(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let x = self.register()? as u16;
+        self.emit_op(opcode | x << 8);
+        Ok(())
+    }
+
+    fn emit_addr_op(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let here = self.here;
+        let target = self.target()?;
+        self.emit_op(opcode);
+        self.resolve(here, target, Fixup::Addr)
+    }
+
+    fn emit_jump_to(&mut self, name: &str, opcode: u16) -> Result<(), Chip8Error> {
+        let here = self.here;
+        self.emit_op(opcode);
+        self.resolve(here, Err(name.to_string()), Fixup::Addr)
+    }
+
+    fn patch_jump(&mut self, addr: usize) {
+        let offset = addr - DEFAULT_START_ADDRESS as usize;
+        let opcode = 0x1000 | self.here as u16;
+        self.rom[offset..offset + 2].copy_from_slice(&opcode.to_be_bytes());
+    }
+
+    // An address operand: a known value, or the name of a label that may be defined later
+    fn target(&mut self) -> Result<Result<usize, String>, Chip8Error> {
+        let token = self.next()?;
+        if let Some(value) = self.number(&token) {
+            return Ok(Ok(value as usize));
+        }
+        if !is_name(&token) {
+            return Err(self.error(format!("expected an address, got '{}'", token)));
+        }
+        Ok(Err(token))
+    }
+
+    // Patches a known address in right away and remembers a label to patch once it is defined
+    fn resolve(&mut self, addr: usize, target: Result<usize, String>, fixup: Fixup) -> Result<(), Chip8Error> {
+        match target {
+            Ok(value) => self.patch(addr, value, fixup),
+            Err(name) => {
+                self.fixups.push((addr, name, fixup, self.line));
+                Ok(())
+            }
+        }
+    }
+
+    fn patch(&mut self, addr: usize, target: usize, fixup: Fixup) -> Result<(), Chip8Error> {
+        let offset = addr - DEFAULT_START_ADDRESS as usize;
+        match fixup {
+            Fixup::Addr => {
+                if target > 0xFFF {
+                    return Err(self.error(format!("{:#X} is above 0xFFF, use 'i := long'", target)));
+                }
+                self.rom[offset] |= (target >> 8) as u8;
+                self.rom[offset + 1] = target as u8;
+            }
+            Fixup::Word => self.rom[offset..offset + 2].copy_from_slice(&(target as u16).to_be_bytes()),
+            Fixup::HighByte(None) => self.rom[offset] = (target >> 8) as u8,
+            Fixup::HighByte(Some(nibble)) => self.rom[offset] = nibble << 4 | ((target >> 8) & 0xF) as u8,
+            Fixup::LowByte => self.rom[offset] = target as u8,
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> Result<(), Chip8Error> {
+        if self.labels.insert(name.clone(), addr).is_some() {
+            return Err(self.error(format!("'{}' is already defined", name)));
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self) -> Result<Operand, Chip8Error> {
+        let token = self.next()?;
+        if let Some(register) = self.register_named(&token) {
+            return Ok(Operand::Register(register));
+        }
+        match self.number(&token) {
+            Some(value) => Ok(Operand::Immediate(self.check_byte(value as i64)?)),
+            None => Err(self.error(format!("expected a register or a number, got '{}'", token))),
+        }
+    }
+
+    fn register(&mut self) -> Result<u8, Chip8Error> {
+        let token = self.next()?;
+        self.register_named(&token)
+            .ok_or_else(|| self.error(format!("expected a register, got '{}'", token)))
+    }
+
+    fn register_named(&self, token: &str) -> Option<u8> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Some(register);
+        }
+        let mut chars = token.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+            _ => None,
+        }
+    }
+
+    fn immediate(&mut self, min: i64, max: i64) -> Result<i64, Chip8Error> {
+        let token = self.next()?;
+        match self.number(&token) {
+            Some(value) if (min as f64..=max as f64).contains(&value) => Ok(value as i64),
+            Some(value) => Err(self.error(format!("{} is outside {}..{}", value, min, max))),
+            None => Err(self.error(format!("expected a number, got '{}'", token))),
+        }
+    }
+
+    fn value(&mut self) -> Result<f64, Chip8Error> {
+        let token = self.next()?;
+        self.number(&token)
+            .ok_or_else(|| self.error(format!("expected a number, got '{}'", token)))
+    }
+
+    fn value_or_calc(&mut self) -> Result<f64, Chip8Error> {
+        if self.peek() == Some("{") {
+            self.calc_block()
+        } else {
+            self.value()
+        }
+    }
+
+    // A literal, constant or already defined label
+    fn number(&self, token: &str) -> Option<f64> {
+        if let Some(&value) = self.constants.get(token) {
+            return Some(value);
+        }
+        if let Some(&addr) = self.labels.get(token) {
+            return Some(addr as f64);
+        }
+
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+            i64::from_str_radix(hex, 16).ok()?
+        } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+            i64::from_str_radix(binary, 2).ok()?
+        } else {
+            digits.parse().ok()?
+        };
+        Some(if negative { -value } else { value } as f64)
+    }
+
+    fn check_byte(&self, value: i64) -> Result<u8, Chip8Error> {
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!("{} does not fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn name(&mut self) -> Result<String, Chip8Error> {
+        let token = self.next()?;
+        if !is_name(&token) || KEYWORDS.contains(&token.as_str()) || self.register_named(&token).is_some() {
+            return Err(self.error(format!("'{}' cannot be used as a name", token)));
+        }
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Chip8Error> {
+        let token = self.next()?;
+        if token != expected {
+            return Err(self.error(format!("expected '{}', got '{}'", expected, token)));
+        }
+        Ok(())
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, Chip8Error> {
+        let token = self.tokens.pop_front().ok_or_else(|| self.error("unexpected end of file"))?;
+        self.line = token.line;
+        Ok(token.text)
+    }
+
+    fn error(&self, message: impl Into<String>) -> Chip8Error {
+        Chip8Error::Asm { file: self.file.clone(), line: self.line, message: message.into() }
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    let mut chars = token.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn run(source: &str, cycles: u32) -> Chip8 {
+        let rom = compile_octo(source).unwrap();
+        let mut c8 = Chip8::new();
+        c8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        for _ in 0..cycles {
+            c8.step_instruction().unwrap();
+        }
+        c8
+    }
+
+    #[test]
+    fn compile_should_encode_statements() {
+        let rom = compile_octo(
+            ": main
+                clear
+                v0 := 5  v1 := v0  v2 += 3  v2 -= 1  v3 =- v1
+                i := hex v0  i += v1  sprite v0 v1 5
+                delay := v0  v4 := key  bcd v2  save v3  load v1 - v2
+                return",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rom,
+            vec![
+                0x00, 0xE0, 0x60, 0x05, 0x81, 0x00, 0x72, 0x03, 0x72, 0xFF, 0x83, 0x17, 0xF0, 0x29, 0xF1, 0x1E, 0xD0,
+                0x15, 0xF0, 0x15, 0xF4, 0x0A, 0xF2, 0x33, 0xF3, 0x55, 0x51, 0x23, 0x00, 0xEE,
+            ]
+        );
+    }
+
+    #[test]
+    fn compile_should_jump_to_main_and_resolve_forward_labels() {
+        let rom = compile_octo(
+            ": sprite-data 0xFF 0b10000001
+             : main
+                i := sprite-data
+                draw
+             : draw ;",
+        )
+        .unwrap();
+
+        assert_eq!(rom, vec![0x12, 0x04, 0xFF, 0x81, 0xA2, 0x02, 0x22, 0x08, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn compile_should_expand_macros_aliases_and_calc() {
+        let rom = compile_octo(
+            ":alias counter v3
+             :const SPEED 2
+             :calc DOUBLE { SPEED * 2 + 1 }
+             :macro bump reg amount { reg += amount }
+             : main
+                bump counter DOUBLE
+                :byte { 10 - 4 - 2 }",
+        )
+        .unwrap();
+
+        // Right to left: 2 * (2 + 1) and 10 - (4 - 2)
+        assert_eq!(rom, vec![0x12, 0x02, 0x73, 0x06, 0x08]);
+    }
+
+    #[test]
+    fn loops_and_branches_should_run() {
+        let c8 = run(
+            ": main
+                v0 := 0  v1 := 0
+                loop
+                    v0 += 1
+                    if v0 > 4 begin v1 += 10 else v1 += 1 end
+                    while v0 != 8
+                again
+                v2 := 1
+                if v0 == 8 then v2 := 2
+             : halt jump halt",
+            200,
+        );
+
+        assert_eq!(c8.v[0], 8);
+        assert_eq!(c8.v[1], 4 + 40);
+        assert_eq!(c8.v[2], 2);
+    }
+
+    #[test]
+    fn comparisons_should_match_their_operators() {
+        for (a, b) in [(3u8, 5u8), (5, 5), (7, 5)] {
+            let c8 = run(
+                &format!(
+                    ": main
+                        v0 := {}  v1 := {}  va := 0
+                        if v0 < v1 then va += 1
+                        if v0 > 5 then va += 2
+                        if v0 <= v1 then va += 4
+                        if v0 >= 5 then va += 8
+                     : halt jump halt",
+                    a, b
+                ),
+                20,
+            );
+
+            let expected = (a < b) as u8 + 2 * (a > b) as u8 + 4 * (a <= b) as u8 + 8 * (a >= b) as u8;
+            assert_eq!(c8.v[0xA], expected, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn unpack_should_split_an_address() {
+        let rom = compile_octo(": main :unpack 0xA data : data").unwrap();
+        assert_eq!(rom, vec![0x60, 0xA2, 0x61, 0x04]);
+
+        let rom = compile_octo(": main i := long data :org 0x300 : data").unwrap();
+        assert_eq!(&rom[..4], &[0xF0, 0x00, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn errors_should_point_at_the_source_line() {
+        let err = compile_octo(": main\n  v0 := 1\n  v0 := 300\n").unwrap_err();
+        assert_eq!(err.to_string(), "<input>:3: 300 does not fit in a byte");
+
+        let err = compile_octo(": main\n\n  jump nowhere").unwrap_err();
+        assert_eq!(err.to_string(), "<input>:3: 'nowhere' is not defined");
+
+        assert!(compile_octo("v0 := 1").is_err());
+        assert!(compile_octo(": main loop v0 += 1").is_err());
+    }
+}
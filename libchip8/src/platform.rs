@@ -0,0 +1,351 @@
+use crate::chip8::{InstructionSet, MEMORY_SIZE, XO_MEMORY_SIZE};
+use crate::error::Chip8Error;
+use crate::fonts::{Font, FontSet};
+use crate::quirks::Quirks;
+use std::fmt;
+use std::str::FromStr;
+
+pub const DEFAULT_START_ADDRESS: u16 = 0x200;
+pub const DEFAULT_STACK_DEPTH: usize = 16;
+/// The rate frontends run frames at, and the rate the timers count down at by default.
+pub const FRAME_HZ: u32 = 60;
+/// The stack pointer is 16 bits wide, as it is in save states and over gdb.
+pub const MAX_STACK_DEPTH: usize = u16::MAX as usize;
+
+/// What CALL does with a full stack and RET with an empty one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum StackPolicy {
+    /// Fail with `Chip8Error::StackOverflow` or `Chip8Error::StackUnderflow`.
+    #[default]
+    Error,
+    /// Stop the machine on the faulting instruction, as 00FD would.
+    Halt,
+    /// Treat the stack as a ring, so a CALL overwrites the oldest return address and a RET
+    /// returns to the newest slot.
+    Wrap,
+}
+
+impl StackPolicy {
+    pub const ALL: [StackPolicy; 3] = [StackPolicy::Error, StackPolicy::Halt, StackPolicy::Wrap];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StackPolicy::Error => "error",
+            StackPolicy::Halt => "halt",
+            StackPolicy::Wrap => "wrap",
+        }
+    }
+}
+
+impl fmt::Display for StackPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for StackPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StackPolicy::ALL.into_iter().find(|policy| policy.name().eq_ignore_ascii_case(s)).ok_or_else(|| {
+            let names: Vec<&str> = StackPolicy::ALL.iter().map(|p| p.name()).collect();
+            format!("unknown stack policy '{}', expected one of: {}", s, names.join(", "))
+        })
+    }
+}
+
+/// Everything that shapes a `Chip8` before a rom is loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub instruction_set: InstructionSet,
+    pub quirks: Quirks,
+    pub memory_size: usize,
+    /// Nested CALLs the stack holds.
+    pub stack_depth: usize,
+    pub stack_policy: StackPolicy,
+    /// Where the first instruction executes.
+    pub start_address: u16,
+    /// Where `Chip8::load` and friends copy the rom, usually `start_address` as well.
+    pub load_address: u16,
+    pub font: Font,
+    /// Where the font is loaded, FX29 and FX30 point into it.
+    pub font_address: u16,
+    /// The rate the delay and sound timers count down at, independently of the frame rate.
+    pub timer_hz: u32,
+}
+
+impl Config {
+    /// Checks that a machine can be built from this config: the stack pointer can address the
+    /// stack, the font fits in memory and the timers run. `Chip8::try_with_config` does this before building one.
+    pub fn validate(&self) -> Result<(), Chip8Error> {
+        if !(1..=MAX_STACK_DEPTH).contains(&self.stack_depth) {
+            return Err(Chip8Error::InvalidConfig("the stack depth must be between 1 and 65535"));
+        }
+        let font_len = self.font.small.len() + self.font.big.map_or(0, |big| big.len());
+        if self.font_address as usize + font_len > self.memory_size {
+            return Err(Chip8Error::InvalidConfig("the font does not fit in memory at the font address"));
+        }
+        if self.timer_hz == 0 {
+            return Err(Chip8Error::InvalidConfig("the timer rate must be at least 1 Hz"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            instruction_set: InstructionSet::default(),
+            quirks: Quirks::default(),
+            memory_size: MEMORY_SIZE,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            stack_policy: StackPolicy::default(),
+            start_address: DEFAULT_START_ADDRESS,
+            load_address: DEFAULT_START_ADDRESS,
+            font: Font::default(),
+            font_address: 0,
+            timer_hz: FRAME_HZ,
+        }
+    }
+}
+
+/// Well known interpreters, each selecting a coherent `Config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// The original 1977 interpreter for the RCA COSMAC VIP.
+    CosmacVip,
+    /// CHIP-48 for the HP-48 calculators.
+    Chip48,
+    /// SUPER-CHIP 1.0, which added the hires display to CHIP-48.
+    SChip10,
+    /// SUPER-CHIP 1.1, which added scrolling to SUPER-CHIP 1.0.
+    SChip11,
+    /// XO-CHIP as specified by John Earnest.
+    XoChip,
+    /// The Octo IDE's own profile: XO-CHIP instructions and quirks, but the 4 KB memory of
+    /// CHIP-8 machines and a slower default speed.
+    Octo,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 6] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SChip10,
+        Platform::SChip11,
+        Platform::XoChip,
+        Platform::Octo,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SChip10 => "schip10",
+            Platform::SChip11 => "schip11",
+            Platform::XoChip => "xochip",
+            Platform::Octo => "octo",
+        }
+    }
+
+    /// Instructions per 60 Hz frame that games written for this platform expect.
+    pub fn cycles_per_frame(self) -> u32 {
+        match self {
+            Platform::CosmacVip => 15,
+            Platform::Chip48 | Platform::SChip10 | Platform::SChip11 => 30,
+            Platform::XoChip => 1000,
+            Platform::Octo => 20,
+        }
+    }
+
+    pub fn config(self) -> Config {
+        let legacy = Config {
+            memory_size: MEMORY_SIZE,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            start_address: DEFAULT_START_ADDRESS,
+            load_address: DEFAULT_START_ADDRESS,
+            ..Config::default()
+        };
+        let super_chip_quirks = Quirks { jump_uses_vx: true, ..Quirks::default() };
+        let xo_chip_quirks = Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            sprite_wrap: true,
+            ..Quirks::default()
+        };
+
+        match self {
+            Platform::CosmacVip => Config {
+                instruction_set: InstructionSet::Chip8,
+                quirks: Quirks {
+                    shift_uses_vy: true,
+                    load_store_increments_i: true,
+                    logic_resets_vf: true,
+                    display_wait: true,
+                    ..Quirks::default()
+                },
+                stack_depth: 12,
+                font: FontSet::Vip.font(),
+                ..legacy
+            },
+            Platform::Chip48 => Config {
+                instruction_set: InstructionSet::Chip8,
+                quirks: super_chip_quirks,
+                font: FontSet::Chip48.font(),
+                ..legacy
+            },
+            Platform::SChip10 => Config {
+                instruction_set: InstructionSet::SuperChip10,
+                quirks: super_chip_quirks,
+                font: FontSet::SuperChip.font(),
+                ..legacy
+            },
+            Platform::SChip11 => Config {
+                instruction_set: InstructionSet::SuperChip11,
+                quirks: super_chip_quirks,
+                font: FontSet::SuperChip.font(),
+                ..legacy
+            },
+            Platform::XoChip => Config {
+                instruction_set: InstructionSet::XoChip,
+                quirks: xo_chip_quirks,
+                memory_size: XO_MEMORY_SIZE,
+                ..legacy
+            },
+            // Octo's shift and load/store quirks are off by default, which means VY is shifted
+            // and I advances, the same as XO-CHIP. Its octo profile caps roms at 3584 bytes.
+            Platform::Octo => Config {
+                instruction_set: InstructionSet::XoChip,
+                quirks: xo_chip_quirks,
+                font: FontSet::Octo.font(),
+                ..legacy
+            },
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized = s.to_ascii_lowercase().replace(['-', '_', '.'], "");
+        let platform = match normalized.as_str() {
+            "vip" | "cosmacvip" | "chip8" => Platform::CosmacVip,
+            "chip48" => Platform::Chip48,
+            "schip10" | "superchip10" => Platform::SChip10,
+            "schip11" | "schip" | "superchip11" | "superchip" => Platform::SChip11,
+            "xochip" | "xo" => Platform::XoChip,
+            "octo" => Platform::Octo,
+            _ => {
+                let names: Vec<&str> = Platform::ALL.iter().map(|p| p.name()).collect();
+                return Err(format!("unknown platform '{}', expected one of: {}", s, names.join(", ")));
+            }
+        };
+        Ok(platform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stack_policies_should_parse_their_names() {
+        for policy in StackPolicy::ALL {
+            assert_eq!(policy.name().to_uppercase().parse::<StackPolicy>(), Ok(policy));
+        }
+        assert!("ignore".parse::<StackPolicy>().is_err());
+    }
+
+    #[test]
+    fn validate_should_check_the_stack_depth() {
+        for platform in Platform::ALL {
+            assert!(platform.config().validate().is_ok(), "{}", platform);
+        }
+        for stack_depth in [1, MAX_STACK_DEPTH] {
+            assert!(Config { stack_depth, ..Config::default() }.validate().is_ok());
+        }
+        for stack_depth in [0, MAX_STACK_DEPTH + 1] {
+            let result = Config { stack_depth, ..Config::default() }.validate();
+            assert!(matches!(result, Err(Chip8Error::InvalidConfig(_))), "{}", stack_depth);
+        }
+    }
+
+    #[test]
+    fn validate_should_check_the_font_fits_in_memory() {
+        let config = Config { font_address: (MEMORY_SIZE - 240) as u16, ..Config::default() };
+        assert!(config.validate().is_ok());
+
+        let config = Config { font_address: (MEMORY_SIZE - 239) as u16, ..Config::default() };
+        assert!(matches!(config.validate(), Err(Chip8Error::InvalidConfig(_))));
+
+        // Without the big font only the 80 bytes of the small font have to fit
+        let config = Config { font: FontSet::Vip.font(), font_address: (MEMORY_SIZE - 80) as u16, ..Config::default() };
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn every_platform_should_round_trip_through_its_name() {
+        for platform in Platform::ALL {
+            assert_eq!(platform.name().parse::<Platform>(), Ok(platform));
+        }
+    }
+
+    #[test]
+    fn platform_names_should_ignore_case_and_punctuation() {
+        assert_eq!("SCHIP-1.1".parse::<Platform>(), Ok(Platform::SChip11));
+        assert_eq!("XO-CHIP".parse::<Platform>(), Ok(Platform::XoChip));
+        assert_eq!("Cosmac_VIP".parse::<Platform>(), Ok(Platform::CosmacVip));
+        assert!("gameboy".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn xo_chip_platforms_should_share_the_xo_chip_quirks() {
+        for platform in [Platform::XoChip, Platform::Octo] {
+            let config = platform.config();
+            assert_eq!(config.instruction_set, InstructionSet::XoChip);
+            assert_eq!(
+                config.quirks,
+                Quirks {
+                    shift_uses_vy: true,
+                    load_store_increments_i: true,
+                    jump_uses_vx: false,
+                    logic_resets_vf: false,
+                    sprite_wrap: true,
+                    display_wait: false,
+                },
+                "{}",
+                platform
+            );
+        }
+    }
+
+    #[test]
+    fn octo_should_differ_from_xo_chip_in_memory_and_speed() {
+        assert_eq!(Platform::XoChip.config().memory_size, XO_MEMORY_SIZE);
+        assert_eq!(Platform::Octo.config().memory_size, MEMORY_SIZE);
+        assert_eq!(Platform::Octo.config().font, FontSet::Octo.font());
+        assert!(Platform::Octo.cycles_per_frame() < Platform::XoChip.cycles_per_frame());
+    }
+
+    #[test]
+    fn cosmac_vip_should_use_the_original_quirks() {
+        let config = Platform::CosmacVip.config();
+
+        assert_eq!(config.instruction_set, InstructionSet::Chip8);
+        assert_eq!(config.stack_depth, 12);
+        assert_eq!(config.font, FontSet::Vip.font());
+        assert!(config.font.big.is_none());
+        assert!(config.quirks.shift_uses_vy);
+        assert!(config.quirks.load_store_increments_i);
+        assert!(config.quirks.logic_resets_vf);
+        assert!(config.quirks.display_wait);
+        assert!(!config.quirks.jump_uses_vx);
+    }
+}
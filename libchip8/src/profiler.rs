@@ -0,0 +1,247 @@
+use crate::instruction::Instruction;
+use crate::observer::Observer;
+use std::collections::HashMap;
+use std::fmt;
+
+// Opcode families by their top nibble, named after the usual pattern for each
+const FAMILIES: [&str; 16] = [
+    "0NNN", "1NNN", "2NNN", "3XNN", "4XNN", "5XYN", "6XNN", "7XNN", "8XYN", "9XY0", "ANNN", "BNNN", "CXNN", "DXYN",
+    "EXNN", "FXNN",
+];
+
+// Rows the text report shows for addresses and loops, the JSON report has all of them
+const TEXT_REPORT_ROWS: usize = 20;
+
+/// Counts executed instructions per address and per opcode family, and treats every
+/// backward JP as closing a loop. It is an `Observer`, so attach it to a machine with
+/// `Chip8::set_observer`, usually through an `Rc<RefCell<Profiler>>` to read it later.
+#[derive(Default)]
+pub struct Profiler {
+    addresses: HashMap<u16, (u64, Instruction)>,
+    families: [u64; 16],
+    // Times each backward jump was taken, keyed by (target, jump address)
+    loops: HashMap<(u16, u16), u64>,
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Profiler::default();
+    }
+
+    /// Everything counted so far, sorted by cost.
+    pub fn report(&self) -> ProfileReport {
+        let mut addresses: Vec<AddressProfile> = self
+            .addresses
+            .iter()
+            .map(|(&pc, &(count, instruction))| AddressProfile { pc, instruction, count })
+            .collect();
+        addresses.sort_by(|a, b| b.count.cmp(&a.count).then(a.pc.cmp(&b.pc)));
+
+        let mut families: Vec<(&'static str, u64)> =
+            FAMILIES.iter().copied().zip(self.families).filter(|(_, count)| *count > 0).collect();
+        families.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        // A loop costs whatever ran between its start and its jump back, inner loops included
+        let mut loops: Vec<HotLoop> = self
+            .loops
+            .iter()
+            .map(|(&(start, end), &iterations)| {
+                let cost = (start..=end).filter_map(|pc| self.addresses.get(&pc)).map(|(count, _)| count).sum();
+                HotLoop { start, end, iterations, cost }
+            })
+            .collect();
+        loops.sort_by(|a, b| b.cost.cmp(&a.cost).then(a.start.cmp(&b.start)));
+
+        ProfileReport { total: self.total, addresses, families, loops }
+    }
+}
+
+impl Observer for Profiler {
+    fn on_execute(&mut self, pc: u16, instruction: Instruction) {
+        self.total += 1;
+        self.addresses.entry(pc).or_insert((0, instruction)).0 += 1;
+        self.families[(instruction.encode() >> 12) as usize] += 1;
+
+        if let Instruction::Jp(target) = instruction {
+            if target <= pc {
+                *self.loops.entry((target, pc)).or_insert(0) += 1;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AddressProfile {
+    pub pc: u16,
+    pub instruction: Instruction,
+    pub count: u64,
+}
+
+/// The instructions from `start` to the backward jump at `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HotLoop {
+    pub start: u16,
+    pub end: u16,
+    /// Times the jump back was taken.
+    pub iterations: u64,
+    /// Instructions executed inside the loop.
+    pub cost: u64,
+}
+
+/// A snapshot of a `Profiler`. `Display` prints a text summary of the most expensive
+/// entries, `to_json` every entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProfileReport {
+    pub total: u64,
+    pub addresses: Vec<AddressProfile>,
+    pub families: Vec<(&'static str, u64)>,
+    pub loops: Vec<HotLoop>,
+}
+
+impl ProfileReport {
+    fn share(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let addresses: Vec<String> = self
+            .addresses
+            .iter()
+            .map(|a| {
+                format!(
+                    r#"{{"pc": "0x{:03X}", "instruction": "{}", "count": {}, "percent": {:.2}}}"#,
+                    a.pc,
+                    a.instruction,
+                    a.count,
+                    self.share(a.count)
+                )
+            })
+            .collect();
+        let families: Vec<String> = self
+            .families
+            .iter()
+            .map(|(family, count)| {
+                format!(r#"{{"family": "{}", "count": {}, "percent": {:.2}}}"#, family, count, self.share(*count))
+            })
+            .collect();
+        let loops: Vec<String> = self
+            .loops
+            .iter()
+            .map(|l| {
+                format!(
+                    r#"{{"start": "0x{:03X}", "end": "0x{:03X}", "iterations": {}, "cost": {}, "percent": {:.2}}}"#,
+                    l.start,
+                    l.end,
+                    l.iterations,
+                    l.cost,
+                    self.share(l.cost)
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n  \"total\": {},\n  \"addresses\": [\n    {}\n  ],\n  \"families\": [\n    {}\n  ],\n  \"loops\": [\n    {}\n  ]\n}}\n",
+            self.total,
+            addresses.join(",\n    "),
+            families.join(",\n    "),
+            loops.join(",\n    ")
+        )
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions executed", self.total)?;
+
+        writeln!(f, "\nhot loops:")?;
+        for l in self.loops.iter().take(TEXT_REPORT_ROWS) {
+            writeln!(
+                f,
+                "  {:03X}-{:03X}  {:>12} instructions {:>6.2}%  {} iterations",
+                l.start,
+                l.end,
+                l.cost,
+                self.share(l.cost),
+                l.iterations
+            )?;
+        }
+
+        writeln!(f, "\nopcode families:")?;
+        for (family, count) in &self.families {
+            writeln!(f, "  {}  {:>12} {:>6.2}%", family, count, self.share(*count))?;
+        }
+
+        writeln!(f, "\nhot addresses:")?;
+        for a in self.addresses.iter().take(TEXT_REPORT_ROWS) {
+            writeln!(f, "  {:03X}  {:<16} {:>12} {:>6.2}%", a.pc, a.instruction.to_string(), a.count, self.share(a.count))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // 200: LD V0, 0
+    // 202: ADD V0, 1
+    // 204: SE V0, 3
+    // 206: JP 202
+    // 208: JP 208
+    fn profile(steps: usize) -> ProfileReport {
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut c8 = Chip8::new();
+        c8.write_memory(0x200, &[0x60, 0x00, 0x70, 0x01, 0x30, 0x03, 0x12, 0x02, 0x12, 0x08]).unwrap();
+        c8.set_observer(Some(Box::new(profiler.clone())));
+        for _ in 0..steps {
+            c8.step_instruction().unwrap();
+        }
+        let report = profiler.borrow().report();
+        report
+    }
+
+    #[test]
+    fn report_should_count_addresses_and_families() {
+        let report = profile(18);
+
+        assert_eq!(report.total, 18);
+        assert_eq!(report.addresses[0], AddressProfile { pc: 0x208, instruction: Instruction::Jp(0x208), count: 9 });
+        assert_eq!(report.addresses.iter().find(|a| a.pc == 0x202).unwrap().count, 3);
+        assert_eq!(report.families[0], ("1NNN", 11));
+        assert!(report.families.contains(&("3XNN", 3)));
+    }
+
+    #[test]
+    fn report_should_find_hot_loops_from_backward_jumps() {
+        let report = profile(18);
+
+        assert_eq!(report.loops.len(), 2);
+        assert_eq!(report.loops[0], HotLoop { start: 0x208, end: 0x208, iterations: 9, cost: 9 });
+        assert_eq!(report.loops[1], HotLoop { start: 0x202, end: 0x206, iterations: 2, cost: 8 });
+    }
+
+    #[test]
+    fn report_should_render_as_text_and_json() {
+        let report = profile(18);
+
+        let text = report.to_string();
+        assert!(text.starts_with("18 instructions executed"));
+        assert!(text.contains("  202-206             8 instructions  44.44%  2 iterations"));
+
+        let json = report.to_json();
+        assert!(json.contains(r#"{"pc": "0x208", "instruction": "JP #208", "count": 9, "percent": 50.00}"#));
+        assert!(json.contains(r#"{"start": "0x202", "end": "0x206", "iterations": 2, "cost": 8, "percent": 44.44}"#));
+    }
+}
@@ -0,0 +1,17 @@
+/// Behaviors that differ between CHIP-8 interpreters. The default matches the
+/// behavior of this emulator before quirks were configurable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VY and store the result in VX, instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55 and FX65 leave I pointing one past the last register stored or loaded.
+    pub load_store_increments_i: bool,
+    /// BNNN jumps to XNN plus VX, where X is the highest nibble of NNN, instead of NNN plus V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Sprite pixels past the edge of the screen wrap to the opposite side instead of being clipped.
+    pub sprite_wrap: bool,
+    /// DXYN waits for the next vertical blank, so at most one sprite is drawn per frame.
+    pub display_wait: bool,
+}
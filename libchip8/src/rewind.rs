@@ -0,0 +1,242 @@
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use std::collections::VecDeque;
+
+/// A memory bounded history of save states for stepping a machine backwards.
+///
+/// Only the newest state is kept whole. Every older state is stored as the XOR of it
+/// and the state after it, with runs of unchanged (zero) bytes compressed away, so a
+/// frame that only touched a few registers and pixels costs a few dozen bytes. The
+/// oldest deltas are dropped once the buffer grows past its budget.
+pub struct RewindBuffer {
+    budget: usize,
+    newest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    deltas_size: usize,
+}
+
+impl RewindBuffer {
+    /// Creates an empty buffer that keeps its memory use around `budget` bytes.
+    pub fn new(budget: usize) -> RewindBuffer {
+        RewindBuffer {
+            budget,
+            newest: None,
+            deltas: VecDeque::new(),
+            deltas_size: 0,
+        }
+    }
+
+    /// Records the current state of `c8` as the newest entry.
+    pub fn record(&mut self, c8: &Chip8) {
+        self.push(c8.save_state());
+    }
+
+    /// Restores `c8` to the state recorded before the newest one and forgets the newest.
+    /// Returns false, leaving `c8` alone, when there is nothing older to go back to.
+    pub fn step_back(&mut self, c8: &mut Chip8) -> Result<bool, Chip8Error> {
+        match self.pop() {
+            Some(state) => c8.load_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            let delta = encode_delta(&state, &newest);
+            self.deltas_size += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(state);
+
+        while self.memory_usage() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.deltas_size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Drops the newest state and returns the one recorded before it.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.deltas_size -= delta.len();
+
+        let newest = self.newest.as_mut().expect("deltas always follow a newest state");
+        apply_delta(newest, &delta);
+        Some(newest.clone())
+    }
+
+    /// The number of steps `pop` can still go back.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Bytes held by the recorded states.
+    pub fn memory_usage(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas_size
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.deltas_size = 0;
+    }
+}
+
+// A delta turns `current` back into `previous`. It starts with the length of `previous`,
+// followed by (zero run, literal length, literal bytes) records of `current ^ previous`
+// with the shorter side padded with zeros.
+fn encode_delta(current: &[u8], previous: &[u8]) -> Vec<u8> {
+    let len = current.len().max(previous.len());
+    let xor = (0..len).map(|i| current.get(i).unwrap_or(&0) ^ previous.get(i).unwrap_or(&0));
+
+    let mut out = Vec::new();
+    write_varint(&mut out, previous.len());
+
+    let mut zeros = 0;
+    let mut literal = Vec::new();
+    for byte in xor {
+        if byte == 0 {
+            if !literal.is_empty() {
+                write_run(&mut out, zeros, &literal);
+                zeros = 0;
+                literal.clear();
+            }
+            zeros += 1;
+        } else {
+            literal.push(byte);
+        }
+    }
+    if !literal.is_empty() {
+        write_run(&mut out, zeros, &literal);
+    }
+
+    out
+}
+
+fn apply_delta(state: &mut Vec<u8>, delta: &[u8]) {
+    let mut pos = 0;
+    let previous_len = read_varint(delta, &mut pos);
+    if state.len() < previous_len {
+        state.resize(previous_len, 0);
+    }
+
+    let mut offset = 0;
+    while pos < delta.len() {
+        offset += read_varint(delta, &mut pos);
+        let literal_len = read_varint(delta, &mut pos);
+        for &byte in &delta[pos..pos + literal_len] {
+            state[offset] ^= byte;
+            offset += 1;
+        }
+        pos += literal_len;
+    }
+
+    state.truncate(previous_len);
+}
+
+fn write_run(out: &mut Vec<u8>, zeros: usize, literal: &[u8]) {
+    write_varint(out, zeros);
+    write_varint(out, literal.len());
+    out.extend_from_slice(literal);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_should_walk_back_through_pushed_states() {
+        let states = vec![vec![0u8; 64], vec![1u8; 64], vec![1u8; 200], vec![7u8; 10], vec![7u8; 10]];
+
+        let mut rewind = RewindBuffer::new(usize::MAX);
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.len(), 4);
+
+        for expected in states.iter().rev().skip(1) {
+            assert_eq!(rewind.pop().as_ref(), Some(expected));
+        }
+        assert_eq!(rewind.pop(), None);
+        assert!(rewind.is_empty());
+    }
+
+    #[test]
+    fn small_changes_should_produce_small_deltas() {
+        let mut state = vec![0u8; 4096];
+        let mut rewind = RewindBuffer::new(usize::MAX);
+        rewind.push(state.clone());
+
+        state[100] = 1;
+        state[3000] = 2;
+        rewind.push(state);
+
+        assert!(rewind.memory_usage() < 4096 + 16);
+    }
+
+    #[test]
+    fn push_should_drop_the_oldest_states_past_the_budget() {
+        let mut rewind = RewindBuffer::new(1024 + 64);
+        for i in 0..100u8 {
+            let mut state = vec![0u8; 1024];
+            state[i as usize * 8..i as usize * 8 + 8].fill(i + 1);
+            rewind.push(state);
+        }
+
+        assert!(rewind.memory_usage() <= 1024 + 64);
+        assert!(!rewind.is_empty() && rewind.len() < 99);
+
+        let mut expected = vec![0u8; 1024];
+        expected[98 * 8..98 * 8 + 8].fill(99);
+        assert_eq!(rewind.pop(), Some(expected));
+    }
+
+    #[test]
+    fn step_back_should_restore_the_previous_frame() {
+        let mut c8 = Chip8::new();
+        // ADD V0, 1; JP 0x200
+        c8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+
+        let mut rewind = RewindBuffer::new(1 << 20);
+        rewind.record(&c8);
+        for _ in 0..3 {
+            c8.run_frame(2).unwrap();
+            rewind.record(&c8);
+        }
+        assert_eq!(c8.v[0], 3);
+
+        assert!(rewind.step_back(&mut c8).unwrap());
+        assert_eq!(c8.v[0], 2);
+        assert!(rewind.step_back(&mut c8).unwrap());
+        assert!(rewind.step_back(&mut c8).unwrap());
+        assert_eq!(c8.v[0], 0);
+        assert!(!rewind.step_back(&mut c8).unwrap());
+    }
+}
@@ -0,0 +1,66 @@
+/// SplitMix64 pseudo random number generator used by CXNN. The whole state is a single
+/// u64, so a run can be reproduced from its seed and resumed exactly from a save state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /// Seeds the generator from the operating system, for runs that do not need to be reproduced.
+    pub fn from_entropy() -> Rng {
+        Rng::new(rand::random())
+    }
+
+    /// The current state. Passing it to `Rng::new` continues the same sequence.
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_match_the_splitmix64_reference_output() {
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn same_seed_should_produce_the_same_sequence() {
+        let mut a = Rng::new(538);
+        let mut b = Rng::new(538);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u8(), b.next_u8());
+        }
+    }
+
+    #[test]
+    fn state_should_resume_the_sequence() {
+        let mut rng = Rng::new(42);
+        rng.next_u64();
+
+        let mut resumed = Rng::new(rng.state());
+        assert_eq!(resumed.next_u64(), rng.next_u64());
+    }
+}
@@ -0,0 +1,472 @@
+//! Save state serialization for `Chip8`.
+//!
+//! A save state is a header followed by tagged sections, all integers little endian:
+//!
+//! ```text
+//! header   magic "C8ST" (4 bytes), version (u16), rom hash (u64)
+//! section  id (4 ASCII bytes), length (u32), payload (length bytes)
+//! ```
+//!
+//! Sections follow the header until the end of the data. Readers skip sections with ids
+//! they do not know, so new data can be added in new sections without breaking older
+//! readers. The version only changes when an existing section changes incompatibly.
+//!
+//! | id     | payload                                                                        |
+//! |--------|--------------------------------------------------------------------------------|
+//! | `CONF` | instruction set (u8), memory size (u32), stack depth (u16), quirks (u8), stack policy (u8), font address (u16), load address (u16), timer rate (u32) |
+//! | `REGS` | V0-VF, I (u16), PC (u16), SP (u16), delay (u8), sound (u8), pitch (u8), plane mask (u8), flags (u8), RPL flags (16 bytes), timer phase (u32) |
+//! | `STAK` | the call stack, one u16 per level                                               |
+//! | `MEM ` | all of memory                                                                   |
+//! | `GFX ` | one byte per pixel; the length tells lores from hires                           |
+//! | `KEYS` | the 16 key states                                                               |
+//! | `RNG ` | seed (u64), state (u64)                                                         |
+//! | `AUDI` | the 16 byte XO-CHIP audio pattern                                               |
+//!
+//! The flags byte holds hires (bit 0), halted (bit 1), draw flag (bit 2) and vblank (bit 3).
+//! The quirks byte holds the `Quirks` fields in declaration order from bit 0, and the stack
+//! policy is 0 for error, 1 for halt and 2 for wrap. The timer phase is how far the timers
+//! are towards their next decrement when they run at a rate other than 60 Hz.
+
+use crate::chip8::{Chip8, InstructionSet, AUDIO_PATTERN_SIZE, GFX_HEIGHT, GFX_WIDTH, HIRES_GFX_HEIGHT, HIRES_GFX_WIDTH};
+use crate::error::Chip8Error;
+use crate::platform::{StackPolicy, FRAME_HZ};
+use crate::quirks::Quirks;
+use crate::rng::Rng;
+
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+pub const STATE_VERSION: u16 = 1;
+
+const CONF: [u8; 4] = *b"CONF";
+const REGS: [u8; 4] = *b"REGS";
+const STAK: [u8; 4] = *b"STAK";
+const MEM: [u8; 4] = *b"MEM ";
+const GFX: [u8; 4] = *b"GFX ";
+const KEYS: [u8; 4] = *b"KEYS";
+const RNG: [u8; 4] = *b"RNG ";
+const AUDI: [u8; 4] = *b"AUDI";
+
+const FLAG_HIRES: u8 = 1 << 0;
+const FLAG_HALTED: u8 = 1 << 1;
+const FLAG_DRAW: u8 = 1 << 2;
+const FLAG_VBLANK: u8 = 1 << 3;
+
+/// 64 bit FNV-1a hash, used to tie save states to the rom they were made with.
+pub fn rom_hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+impl Chip8 {
+    /// Captures the complete machine, including the random number generator, in the
+    /// format documented in this module.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.memory.len() + self.gfx.len() + 256);
+        out.extend_from_slice(&STATE_MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash.to_le_bytes());
+
+        let mut conf = vec![instruction_set_id(self.instruction_set)];
+        conf.extend_from_slice(&(self.memory.len() as u32).to_le_bytes());
+        conf.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        conf.extend_from_slice(&self.behavior());
+        write_section(&mut out, CONF, &conf);
+
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.hires, FLAG_HIRES),
+            (self.halted, FLAG_HALTED),
+            (self.draw_flag, FLAG_DRAW),
+            (self.vblank, FLAG_VBLANK),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+
+        let mut regs = self.v.to_vec();
+        regs.extend_from_slice(&self.i.to_le_bytes());
+        regs.extend_from_slice(&self.pc.to_le_bytes());
+        regs.extend_from_slice(&self.sp.to_le_bytes());
+        regs.extend_from_slice(&[self.delay_timer, self.sound_timer, self.pitch, self.plane_mask, flags]);
+        regs.extend_from_slice(&self.rpl);
+        regs.extend_from_slice(&self.timer_phase.to_le_bytes());
+        write_section(&mut out, REGS, &regs);
+
+        let stack: Vec<u8> = self.stack.iter().flat_map(|addr| addr.to_le_bytes()).collect();
+        write_section(&mut out, STAK, &stack);
+        write_section(&mut out, MEM, &self.memory);
+        write_section(&mut out, GFX, &self.gfx);
+        write_section(&mut out, KEYS, &self.input);
+
+        let mut rng = self.seed.to_le_bytes().to_vec();
+        rng.extend_from_slice(&self.rng.state().to_le_bytes());
+        write_section(&mut out, RNG, &rng);
+        write_section(&mut out, AUDI, &self.audio_pattern);
+
+        out
+    }
+
+    /// Restores a state produced by `save_state`. The state has to come from the same rom
+    /// and a machine with the same configuration: instruction set, memory size, stack depth
+    /// and policy, quirks, font and load addresses and timer rate. Nothing is changed when an
+    /// error is returned.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = Reader { data, pos: 0 };
+
+        if reader.take(4)? != STATE_MAGIC {
+            return Err(Chip8Error::InvalidState("not a save state"));
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(Chip8Error::UnsupportedStateVersion { version });
+        }
+        let rom_hash = reader.u64()?;
+        if rom_hash != self.rom_hash {
+            return Err(Chip8Error::StateRomMismatch { expected: self.rom_hash, found: rom_hash });
+        }
+
+        let mut sections = Sections::default();
+        while !reader.is_empty() {
+            let id: [u8; 4] = reader.take(4)?.try_into().unwrap();
+            let len = reader.u32()? as usize;
+            let payload = reader.take(len)?;
+
+            match id {
+                CONF => sections.conf = Some(payload),
+                REGS => sections.regs = Some(payload),
+                STAK => sections.stack = Some(payload),
+                MEM => sections.memory = Some(payload),
+                GFX => sections.gfx = Some(payload),
+                KEYS => sections.keys = Some(payload),
+                RNG => sections.rng = Some(payload),
+                AUDI => sections.audio = Some(payload),
+                _ => {} // Written by a newer version, safe to ignore
+            }
+        }
+
+        let mut conf = Reader::section(sections.conf, "missing CONF section")?;
+        let instruction_set = conf.u8()?;
+        let memory_size = conf.u32()? as usize;
+        let stack_depth = conf.u16()? as usize;
+        let behavior = conf.take(BEHAVIOR_LEN)?;
+        if instruction_set != instruction_set_id(self.instruction_set)
+            || memory_size != self.memory.len()
+            || stack_depth != self.stack.len()
+            || behavior != self.behavior()
+        {
+            return Err(Chip8Error::InvalidState("saved on a differently configured machine"));
+        }
+
+        let mut regs = Reader::section(sections.regs, "missing REGS section")?;
+        let v: [u8; 16] = regs.take(16)?.try_into().unwrap();
+        let i = regs.u16()?;
+        let pc = regs.u16()?;
+        let sp = regs.u16()?;
+        let [delay_timer, sound_timer, pitch, plane_mask, flags]: [u8; 5] = regs.take(5)?.try_into().unwrap();
+        let rpl: [u8; 16] = regs.take(16)?.try_into().unwrap();
+        let timer_phase = regs.u32()?;
+        if sp as usize > stack_depth {
+            return Err(Chip8Error::InvalidState("stack pointer past the end of the stack"));
+        }
+        if timer_phase >= FRAME_HZ {
+            return Err(Chip8Error::InvalidState("timer phase past a whole frame"));
+        }
+
+        let stack_bytes = Reader::section(sections.stack, "missing STAK section")?.take(stack_depth * 2)?;
+        let stack: Vec<u16> = stack_bytes.chunks(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+
+        let memory = Reader::section(sections.memory, "missing MEM section")?.take(memory_size)?;
+
+        let hires = flags & FLAG_HIRES != 0;
+        let gfx_len = if hires { HIRES_GFX_WIDTH * HIRES_GFX_HEIGHT } else { GFX_WIDTH * GFX_HEIGHT };
+        let gfx = Reader::section(sections.gfx, "missing GFX section")?.take(gfx_len)?;
+
+        let keys: [u8; 16] = Reader::section(sections.keys, "missing KEYS section")?.take(16)?.try_into().unwrap();
+
+        let mut rng = Reader::section(sections.rng, "missing RNG section")?;
+        let seed = rng.u64()?;
+        let rng_state = rng.u64()?;
+
+        let audio: [u8; AUDIO_PATTERN_SIZE] = Reader::section(sections.audio, "missing AUDI section")?
+            .take(AUDIO_PATTERN_SIZE)?
+            .try_into()
+            .unwrap();
+
+        self.v = v;
+        self.i = i;
+        self.pc = pc;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.pitch = pitch;
+        self.plane_mask = plane_mask;
+        self.hires = hires;
+        self.halted = flags & FLAG_HALTED != 0;
+        self.halt_reason = None;
+        self.draw_flag = flags & FLAG_DRAW != 0;
+        self.vblank = flags & FLAG_VBLANK != 0;
+        self.rpl = rpl;
+        self.timer_phase = timer_phase;
+        self.stack = stack;
+        self.memory.copy_from_slice(memory);
+        self.gfx = gfx.to_vec();
+        self.input = keys;
+        self.seed = seed;
+        self.rng = Rng::new(rng_state);
+        self.audio_pattern = audio;
+
+        Ok(())
+    }
+}
+
+// Quirks, stack policy, font address, load address and timer rate
+const BEHAVIOR_LEN: usize = 1 + 1 + 2 + 2 + 4;
+
+impl Chip8 {
+    // The CONF fields after the stack depth, everything else that changes how a rom runs
+    fn behavior(&self) -> [u8; BEHAVIOR_LEN] {
+        let mut out = [0; BEHAVIOR_LEN];
+        out[0] = quirks_bits(self.quirks);
+        out[1] = match self.stack_policy {
+            StackPolicy::Error => 0,
+            StackPolicy::Halt => 1,
+            StackPolicy::Wrap => 2,
+        };
+        out[2..4].copy_from_slice(&self.font_address.to_le_bytes());
+        out[4..6].copy_from_slice(&self.load_address.to_le_bytes());
+        out[6..10].copy_from_slice(&self.timer_hz.to_le_bytes());
+        out
+    }
+}
+
+fn quirks_bits(quirks: Quirks) -> u8 {
+    [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.sprite_wrap,
+        quirks.display_wait,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, &set)| bits | (set as u8) << bit)
+}
+
+fn instruction_set_id(instruction_set: InstructionSet) -> u8 {
+    match instruction_set {
+        InstructionSet::Chip8 => 0,
+        InstructionSet::SuperChip10 => 1,
+        InstructionSet::SuperChip11 => 2,
+        InstructionSet::XoChip => 3,
+    }
+}
+
+fn write_section(out: &mut Vec<u8>, id: [u8; 4], payload: &[u8]) {
+    out.extend_from_slice(&id);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+#[derive(Default)]
+struct Sections<'a> {
+    conf: Option<&'a [u8]>,
+    regs: Option<&'a [u8]>,
+    stack: Option<&'a [u8]>,
+    memory: Option<&'a [u8]>,
+    gfx: Option<&'a [u8]>,
+    keys: Option<&'a [u8]>,
+    rng: Option<&'a [u8]>,
+    audio: Option<&'a [u8]>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn section(payload: Option<&'a [u8]>, missing: &'static str) -> Result<Reader<'a>, Chip8Error> {
+        let data = payload.ok_or(Chip8Error::InvalidState(missing))?;
+        Ok(Reader { data, pos: 0 })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(Chip8Error::InvalidState("unexpected end of data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::{Config, Platform};
+
+    fn running_machine() -> Chip8 {
+        let mut c8 = Chip8::new();
+        c8.set_seed(99);
+        // LD V0, 0x2A; CALL 0x300; ... 0x300: RND V1, 0xFF; DRW V0, V0, 5; JP 0x302
+        c8.memory[0x200..0x204].copy_from_slice(&[0x60, 0x2A, 0x23, 0x00]);
+        c8.memory[0x300..0x306].copy_from_slice(&[0xC1, 0xFF, 0xD0, 0x05, 0x13, 0x02]);
+        c8.delay_timer = 30;
+        c8.input[5] = 1;
+        c8.run_frame(4).unwrap();
+        c8
+    }
+
+    #[test]
+    fn load_state_should_restore_everything_save_state_captured() {
+        let original = running_machine();
+        let state = original.save_state();
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+
+        assert_eq!(restored.memory, original.memory);
+        assert_eq!(restored.v, original.v);
+        assert_eq!(restored.stack, original.stack);
+        assert_eq!(restored.gfx, original.gfx);
+        assert_eq!(restored.input, original.input);
+        assert_eq!((restored.i, restored.pc, restored.sp), (original.i, original.pc, original.sp));
+        assert_eq!(restored.delay_timer, original.delay_timer);
+        assert_eq!(restored.seed(), 99);
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn load_state_should_resume_the_random_sequence() {
+        let mut original = running_machine();
+        let state = original.save_state();
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+
+        for _ in 0..8 {
+            assert_eq!(restored.rng.next_u8(), original.rng.next_u8());
+        }
+    }
+
+    #[test]
+    fn load_state_should_reject_other_data() {
+        let mut c8 = Chip8::new();
+        let result = c8.load_state(b"not a save state at all");
+        assert!(matches!(result, Err(Chip8Error::InvalidState("not a save state"))));
+    }
+
+    #[test]
+    fn load_state_should_reject_unknown_versions() {
+        let mut state = running_machine().save_state();
+        state[4..6].copy_from_slice(&2u16.to_le_bytes());
+
+        let result = Chip8::new().load_state(&state);
+        assert!(matches!(result, Err(Chip8Error::UnsupportedStateVersion { version: 2 })));
+    }
+
+    #[test]
+    fn load_state_should_reject_states_of_other_roms() {
+        let state = running_machine().save_state();
+        let mut c8 = Chip8::new();
+        c8.rom_hash = rom_hash(b"another rom");
+
+        let result = c8.load_state(&state);
+        assert!(matches!(result, Err(Chip8Error::StateRomMismatch { .. })));
+    }
+
+    #[test]
+    fn load_state_should_reject_other_machine_configurations() {
+        let state = running_machine().save_state();
+        let mut c8 = Chip8::with_platform(Platform::XoChip);
+
+        let result = c8.load_state(&state);
+        assert!(matches!(result, Err(Chip8Error::InvalidState(_))));
+    }
+
+    #[test]
+    fn load_state_should_reject_other_quirks_policies_and_addresses() {
+        let state = running_machine().save_state();
+        let configs = [
+            Config { quirks: Quirks { display_wait: true, ..Quirks::default() }, ..Config::default() },
+            Config { stack_policy: StackPolicy::Wrap, ..Config::default() },
+            Config { font_address: 0x50, ..Config::default() },
+            Config { load_address: 0x600, ..Config::default() },
+            Config { timer_hz: 50, ..Config::default() },
+        ];
+
+        for config in configs {
+            let mut c8 = Chip8::with_config(config);
+            let result = c8.load_state(&state);
+            assert!(matches!(result, Err(Chip8Error::InvalidState(_))), "{:?}", config);
+        }
+    }
+
+    #[test]
+    fn load_state_should_resume_the_timers_mid_phase() {
+        let config = Config { timer_hz: 50, ..Config::default() };
+        let mut original = Chip8::with_config(config);
+        original.delay_timer = 100;
+        for _ in 0..7 {
+            original.tick_timers();
+        }
+        let mut restored = Chip8::with_config(config);
+        restored.load_state(&original.save_state()).unwrap();
+        assert_eq!(restored.timer_phase, original.timer_phase);
+
+        for _ in 0..12 {
+            original.tick_timers();
+            restored.tick_timers();
+            assert_eq!(restored.delay_timer, original.delay_timer);
+        }
+    }
+
+    #[test]
+    fn load_state_should_leave_the_machine_untouched_on_truncated_data() {
+        let state = running_machine().save_state();
+        let mut c8 = Chip8::new();
+
+        let result = c8.load_state(&state[..state.len() - 1]);
+        assert!(matches!(result, Err(Chip8Error::InvalidState("unexpected end of data"))));
+        assert_eq!(c8.pc, 0x200);
+        assert_eq!(c8.v, [0; 16]);
+    }
+
+    #[test]
+    fn load_state_should_skip_unknown_sections() {
+        let original = running_machine();
+        let mut state = original.save_state();
+        write_section(&mut state, *b"NEW!", &[1, 2, 3]);
+
+        let mut restored = Chip8::new();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.pc, original.pc);
+    }
+
+    #[test]
+    fn rom_hash_should_match_the_fnv1a_reference() {
+        assert_eq!(rom_hash(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(rom_hash(b"a"), 0xAF63_DC4C_8601_EC8C);
+    }
+}
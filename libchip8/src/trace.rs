@@ -0,0 +1,215 @@
+use crate::chip8::Chip8;
+use crate::instruction::Instruction;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+// Wide enough for the longest mnemonic, so the register columns line up
+const MNEMONIC_WIDTH: usize = 16;
+// V0-VF, I, SP, DT and ST
+const STATE_FIELDS: usize = 20;
+
+/// One instruction and the machine state it executes in, before any of its effects.
+///
+/// Capturing the state before execution matches the usual reference traces, and lets the
+/// trace end with an instruction that faulted, which is the one worth comparing.
+///
+/// Its `Display` is a line of the trace format, `pc opcode mnemonic V0..VF I SP DT ST` in
+/// hex, and `FromStr` reads one back. The mnemonic is free text that comparisons ignore, so
+/// traces converted from other emulators only need the numeric columns to match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl TraceEntry {
+    pub(crate) fn capture(c8: &Chip8, pc: u16, opcode: u16) -> TraceEntry {
+        let mnemonic = match Instruction::decode(opcode) {
+            Some(instruction) => instruction.to_string(),
+            None => format!("DW #{:04X}", opcode),
+        };
+        TraceEntry {
+            pc,
+            opcode,
+            mnemonic,
+            v: c8.v,
+            i: c8.i,
+            sp: c8.sp,
+            delay_timer: c8.delay_timer,
+            sound_timer: c8.sound_timer,
+        }
+    }
+
+    /// Names the columns that differ from `other`, ignoring the mnemonic.
+    pub fn differences(&self, other: &TraceEntry) -> Vec<String> {
+        let mut fields = Vec::new();
+        if self.pc != other.pc {
+            fields.push("PC".to_string());
+        }
+        if self.opcode != other.opcode {
+            fields.push("opcode".to_string());
+        }
+        for x in 0..16 {
+            if self.v[x] != other.v[x] {
+                fields.push(format!("V{:X}", x));
+            }
+        }
+        let rest = [
+            ("I", self.i == other.i),
+            ("SP", self.sp == other.sp),
+            ("DT", self.delay_timer == other.delay_timer),
+            ("ST", self.sound_timer == other.sound_timer),
+        ];
+        fields.extend(rest.iter().filter(|(_, same)| !same).map(|(name, _)| name.to_string()));
+        fields
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} {:04X} {:<width$}", self.pc, self.opcode, self.mnemonic, width = MNEMONIC_WIDTH)?;
+        for v in self.v {
+            write!(f, " {:02X}", v)?;
+        }
+        write!(f, " {:04X} {:02X} {:02X} {:02X}", self.i, self.sp, self.delay_timer, self.sound_timer)
+    }
+}
+
+impl FromStr for TraceEntry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<&str> = s.split_whitespace().collect();
+        if tokens.len() < 3 + STATE_FIELDS {
+            return Err(format!("expected at least {} columns, found {}", 3 + STATE_FIELDS, tokens.len()));
+        }
+
+        let (head, state) = tokens.split_at(tokens.len() - STATE_FIELDS);
+        let hex = |token: &str| u16::from_str_radix(token, 16).map_err(|_| format!("'{}' is not a hex number", token));
+        let byte = |token: &str| u8::from_str_radix(token, 16).map_err(|_| format!("'{}' is not a hex byte", token));
+
+        let mut v = [0; 16];
+        for (x, token) in state[..16].iter().enumerate() {
+            v[x] = byte(token)?;
+        }
+        Ok(TraceEntry {
+            pc: hex(head[0])?,
+            opcode: hex(head[1])?,
+            mnemonic: head[2..].join(" "),
+            v,
+            i: hex(state[16])?,
+            sp: hex(state[17])?,
+            delay_timer: byte(state[18])?,
+            sound_timer: byte(state[19])?,
+        })
+    }
+}
+
+/// Receives every instruction `Chip8::step_instruction` is about to execute. An error stops
+/// the emulation with `Chip8Error::Io` before the instruction runs.
+pub trait TraceSink {
+    fn trace(&mut self, entry: &TraceEntry) -> io::Result<()>;
+}
+
+/// Writes a trace one line per instruction.
+pub struct TraceWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        TraceWriter { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn trace(&mut self, entry: &TraceEntry) -> io::Result<()> {
+        writeln!(self.writer, "{}", entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl TraceSink for SharedWriter {
+        fn trace(&mut self, entry: &TraceEntry) -> io::Result<()> {
+            writeln!(self.0.borrow_mut(), "{}", entry)
+        }
+    }
+
+    #[test]
+    fn entries_should_round_trip_through_the_text_format() {
+        let mut entry = TraceEntry::capture(&Chip8::new(), 0x2A4, 0xD125);
+        entry.v[0xF] = 1;
+        entry.i = 0x3C0;
+
+        let line = entry.to_string();
+        assert_eq!(
+            line,
+            "02A4 D125 DRW V1, V2, 5    00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 01 03C0 00 00 00"
+        );
+        assert_eq!(line.parse::<TraceEntry>().unwrap(), entry);
+        assert!("02A4 D125 DRW 00".parse::<TraceEntry>().is_err());
+    }
+
+    #[test]
+    fn differences_should_ignore_the_mnemonic() {
+        let entry = TraceEntry::capture(&Chip8::new(), 0x200, 0x6305);
+        let mut other = entry.clone();
+        other.mnemonic = "MOV V3, 5".to_string();
+        assert!(entry.differences(&other).is_empty());
+
+        other.v[3] = 5;
+        other.sound_timer = 1;
+        assert_eq!(entry.differences(&other), vec!["V3", "ST"]);
+    }
+
+    #[test]
+    fn step_instruction_should_trace_the_state_before_each_instruction() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut c8 = Chip8::new();
+        c8.memory[0x200..0x204].copy_from_slice(&[0x63, 0x05, 0xA3, 0x00]);
+        c8.set_trace_sink(Some(Box::new(SharedWriter(lines.clone()))));
+
+        c8.step_instruction().unwrap();
+        c8.step_instruction().unwrap();
+
+        let text = String::from_utf8(lines.borrow().clone()).unwrap();
+        let entries: Vec<TraceEntry> = text.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].pc, entries[0].opcode, entries[0].v[3]), (0x200, 0x6305, 0));
+        assert_eq!((entries[1].pc, entries[1].mnemonic.as_str(), entries[1].v[3]), (0x202, "LD I, #300", 5));
+        assert_eq!(entries[1].i, 0);
+    }
+
+    #[test]
+    fn step_instruction_should_trace_an_instruction_that_faults() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut c8 = Chip8::new();
+        // RET with nothing on the stack
+        c8.memory[0x200..0x202].copy_from_slice(&[0x00, 0xEE]);
+        c8.set_trace_sink(Some(Box::new(SharedWriter(lines.clone()))));
+
+        assert!(c8.step_instruction().is_err());
+
+        let text = String::from_utf8(lines.borrow().clone()).unwrap();
+        let entry: TraceEntry = text.trim_end().parse().unwrap();
+        assert_eq!((entry.pc, entry.opcode), (0x200, 0x00EE));
+    }
+}
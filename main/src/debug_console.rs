@@ -0,0 +1,235 @@
+use chip8::{Breakpoint, Chip8, Debugger, Resume, Stop, Watchpoint};
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::{process, thread};
+
+const HELP: &str = "commands:
+    c, continue             run until a breakpoint or watchpoint
+    s, step                 execute one instruction
+    n, next                 execute one instruction, running calls to completion
+    finish                  run until the current subroutine returns
+    pause                   stop a running rom
+    b, break ADDR [if COND] stop at ADDR, or only when COND holds, e.g. break 2A4 if V3 == 10
+    break if COND           stop at any address when COND holds
+    watch ADDR [LEN]        stop after a write to memory, rwatch for reads, awatch for both
+    delete [ID]             remove a breakpoint or watchpoint, or all of them
+    info                    list breakpoints and watchpoints
+    r, regs                 print registers and timers
+    stack                   print the return addresses on the stack
+    x ADDR [LEN]            dump memory
+    l, list                 disassemble around pc
+    q, quit                 exit
+
+Addresses, lengths and the numbers in conditions are hex, with an optional # or 0x prefix.";
+
+/// The `--debug` REPL. Commands are read from stdin on another thread so the window keeps
+/// drawing and taking input while the rom is paused.
+pub struct DebugConsole {
+    debugger: Debugger,
+    commands: Receiver<String>,
+}
+
+impl DebugConsole {
+    pub fn new(c8: &Chip8) -> DebugConsole {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        eprintln!("Paused, type help for a list of commands");
+        print_location(c8);
+        prompt();
+        DebugConsole { debugger: Debugger::new(), commands }
+    }
+
+    /// Runs any commands typed since the last frame, then a frame of the rom unless it is
+    /// paused. Returns true if the rom ran.
+    pub fn run_frame(&mut self, c8: &mut Chip8, cycles_per_frame: u32) -> bool {
+        // Once stdin closes nothing can resume the rom, but the window stays up
+        while let Ok(line) = self.commands.try_recv() {
+            self.execute(c8, &line);
+            if self.debugger.is_paused() {
+                prompt();
+            }
+        }
+        if self.debugger.is_paused() {
+            return false;
+        }
+
+        match self.debugger.run_frame(c8, cycles_per_frame) {
+            Ok(Some(stop)) => {
+                eprintln!("{}", stop);
+                if !matches!(stop, Stop::Step { .. }) {
+                    eprintln!("{}", Debugger::format_registers(c8));
+                }
+                print_location(c8);
+                prompt();
+            }
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Emulation stopped: {}", e);
+                print_location(c8);
+                prompt();
+            }
+        }
+        true
+    }
+
+    fn execute(&mut self, c8: &mut Chip8, line: &str) {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return;
+        };
+        let args: Vec<&str> = words.collect();
+
+        let result = match command {
+            "c" | "continue" => self.resume(c8, Resume::Continue),
+            "s" | "step" => self.resume(c8, Resume::Step),
+            "n" | "next" => self.resume(c8, Resume::StepOver),
+            "finish" => self.resume(c8, Resume::StepOut),
+            "pause" => {
+                self.debugger.pause();
+                eprintln!("Paused");
+                print_location(c8);
+                Ok(())
+            }
+            "b" | "break" => self.add_breakpoint(&args),
+            "watch" => self.add_watchpoint(&args, false, true),
+            "rwatch" => self.add_watchpoint(&args, true, false),
+            "awatch" => self.add_watchpoint(&args, true, true),
+            "delete" => self.delete(&args),
+            "info" => {
+                self.print_points();
+                Ok(())
+            }
+            "r" | "regs" => {
+                eprintln!("{}", Debugger::format_registers(c8));
+                Ok(())
+            }
+            "stack" => {
+                eprintln!("{}", Debugger::format_stack(c8));
+                Ok(())
+            }
+            "x" => dump_memory(c8, &args),
+            "l" | "list" => {
+                eprintln!("{}", Debugger::format_disassembly(c8, 8, 8));
+                Ok(())
+            }
+            "help" => {
+                eprintln!("{}", HELP);
+                Ok(())
+            }
+            "q" | "quit" => process::exit(0),
+            _ => Err(format!("unknown command '{}', type help for a list of commands", command)),
+        };
+
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+    }
+
+    fn resume(&mut self, c8: &Chip8, resume: Resume) -> Result<(), String> {
+        if c8.is_halted() {
+            return Err("the rom has halted".to_string());
+        }
+        self.debugger.resume(c8, resume);
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str]) -> Result<(), String> {
+        let (addr, condition) = match args.iter().position(|arg| *arg == "if") {
+            Some(index) => (&args[..index], Some(args[index + 1..].join(" "))),
+            None => (args, None),
+        };
+        let breakpoint = Breakpoint {
+            addr: match addr {
+                [] => None,
+                [addr] => Some(parse_number(addr)?),
+                _ => return Err("usage: break ADDR [if COND]".to_string()),
+            },
+            condition: condition.map(|c| c.parse()).transpose()?,
+        };
+        if breakpoint.addr.is_none() && breakpoint.condition.is_none() {
+            return Err("usage: break ADDR [if COND]".to_string());
+        }
+
+        let id = self.debugger.add_breakpoint(breakpoint);
+        eprintln!("Breakpoint {}", id);
+        Ok(())
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str], read: bool, write: bool) -> Result<(), String> {
+        let (addr, len) = match args {
+            [addr] => (parse_number(addr)?, 1),
+            [addr, len] => (parse_number(addr)?, parse_number(len)?),
+            _ => return Err("usage: watch ADDR [LEN]".to_string()),
+        };
+
+        let id = self.debugger.add_watchpoint(Watchpoint { addr, len, read, write });
+        eprintln!("Watchpoint {}", id);
+        Ok(())
+    }
+
+    fn delete(&mut self, args: &[&str]) -> Result<(), String> {
+        match args {
+            [] => {
+                self.debugger.clear();
+                Ok(())
+            }
+            [id] => {
+                let id = id.parse().map_err(|_| format!("'{}' is not an id", id))?;
+                if self.debugger.remove(id) {
+                    Ok(())
+                } else {
+                    Err(format!("no breakpoint or watchpoint {}", id))
+                }
+            }
+            _ => Err("usage: delete [ID]".to_string()),
+        }
+    }
+
+    fn print_points(&self) {
+        for (id, breakpoint) in self.debugger.breakpoints() {
+            let addr = breakpoint.addr.map(|addr| format!(" at {:03X}", addr)).unwrap_or_default();
+            let condition = breakpoint.condition.map(|c| format!(" if {}", c)).unwrap_or_default();
+            eprintln!("{}: breakpoint{}{}", id, addr, condition);
+        }
+        for (id, watchpoint) in self.debugger.watchpoints() {
+            let kind = match (watchpoint.read, watchpoint.write) {
+                (true, true) => "access",
+                (true, false) => "read",
+                _ => "write",
+            };
+            eprintln!("{}: {} watchpoint at {:03X}, {} bytes", id, kind, watchpoint.addr, watchpoint.len);
+        }
+    }
+}
+
+fn dump_memory(c8: &Chip8, args: &[&str]) -> Result<(), String> {
+    let (addr, len) = match args {
+        [addr] => (parse_number(addr)?, 16),
+        [addr, len] => (parse_number(addr)?, parse_number(len)?),
+        _ => return Err("usage: x ADDR [LEN]".to_string()),
+    };
+    eprintln!("{}", Debugger::format_memory(c8, addr, len));
+    Ok(())
+}
+
+fn print_location(c8: &Chip8) {
+    eprintln!("{}", Debugger::format_disassembly(c8, 2, 3));
+}
+
+fn prompt() {
+    eprint!("(chip8) ");
+    let _ = io::stderr().flush();
+}
+
+// Addresses are hex like the listings, with an optional # or 0x prefix
+fn parse_number(s: &str) -> Result<u16, String> {
+    let hex = s.strip_prefix("0x").or(s.strip_prefix('#')).unwrap_or(s);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("'{}' is not a hex number", s))
+}
@@ -0,0 +1,202 @@
+use std::hint;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// thread::sleep can overshoot by a millisecond or more, so we wake up this long
+// before the deadline and spin for the remainder
+const SPIN_THRESHOLD: Duration = Duration::from_millis(2);
+
+// When the host stalls for longer than this many frames the missed frames are
+// dropped instead of being emulated back to back
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct FrameStats {
+    /// Frames presented per second.
+    pub fps: f64,
+    /// Emulated frames per second relative to the target rate, 1.0 is full speed.
+    pub speed: f64,
+    /// How late the most recent frame started.
+    pub drift: Duration,
+    /// Frames dropped since the pacer was created because the host fell too far behind.
+    pub skipped_frames: u64,
+}
+
+/// Paces the emulator against a wall clock target rate. Frame deadlines are absolute,
+/// so oversleeping one frame shortens the next instead of accumulating drift.
+pub struct FramePacer {
+    frame_duration: Duration,
+    next_deadline: Instant,
+    drift: Duration,
+    skipped_frames: u64,
+
+    stats_start: Instant,
+    presented_frames: u32,
+    emulated_frames: u32,
+    stats: Option<FrameStats>,
+}
+
+impl FramePacer {
+    pub fn new(target_hz: u32) -> FramePacer {
+        FramePacer::starting_at(target_hz, Instant::now())
+    }
+
+    // The pacer reads the clock only while sleeping, so tests can drive it with made up instants
+    fn starting_at(target_hz: u32, now: Instant) -> FramePacer {
+        let frame_duration = Duration::from_secs(1) / target_hz;
+
+        FramePacer {
+            frame_duration,
+            next_deadline: now + frame_duration,
+            drift: Duration::ZERO,
+            skipped_frames: 0,
+
+            stats_start: now,
+            presented_frames: 0,
+            emulated_frames: 0,
+            stats: None,
+        }
+    }
+
+    /// Blocks until the next frame is due and returns how many frames to emulate before
+    /// presenting: 1 normally, more when catching up after the host was briefly late.
+    pub fn wait_for_next_frame(&mut self) -> u32 {
+        Self::sleep_until(self.next_deadline);
+        self.frames_due(Instant::now())
+    }
+
+    fn frames_due(&mut self, now: Instant) -> u32 {
+        self.drift = now.saturating_duration_since(self.next_deadline);
+
+        let late_frames = (self.drift.as_nanos() / self.frame_duration.as_nanos()) as u32;
+        let mut frames = 1 + late_frames;
+
+        if frames > MAX_CATCH_UP_FRAMES {
+            self.skipped_frames += (frames - MAX_CATCH_UP_FRAMES) as u64;
+            frames = MAX_CATCH_UP_FRAMES;
+            // Resynchronise with the wall clock rather than racing to make up the stall
+            self.next_deadline = now + self.frame_duration;
+        } else {
+            self.next_deadline += self.frame_duration * frames;
+        }
+
+        self.update_stats(now, frames);
+        frames
+    }
+
+    /// Returns fresh statistics about once per second, and `None` in between.
+    pub fn poll_stats(&mut self) -> Option<FrameStats> {
+        self.stats.take()
+    }
+
+    fn update_stats(&mut self, now: Instant, frames: u32) {
+        self.presented_frames += 1;
+        self.emulated_frames += frames;
+
+        let elapsed = now - self.stats_start;
+        if elapsed < STATS_INTERVAL {
+            return;
+        }
+
+        let seconds = elapsed.as_secs_f64();
+        let target_frames = seconds / self.frame_duration.as_secs_f64();
+        self.stats = Some(FrameStats {
+            fps: self.presented_frames as f64 / seconds,
+            speed: self.emulated_frames as f64 / target_frames,
+            drift: self.drift,
+            skipped_frames: self.skipped_frames,
+        });
+
+        self.stats_start = now;
+        self.presented_frames = 0;
+        self.emulated_frames = 0;
+    }
+
+    fn sleep_until(deadline: Instant) {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return;
+            }
+
+            let remaining = deadline - now;
+            if remaining > SPIN_THRESHOLD {
+                thread::sleep(remaining - SPIN_THRESHOLD);
+            } else {
+                hint::spin_loop();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(10);
+
+    fn pacer() -> (FramePacer, Instant) {
+        let start = Instant::now();
+        (FramePacer::starting_at(100, start), start)
+    }
+
+    #[test]
+    fn frames_due_should_run_one_frame_per_deadline() {
+        let (mut pacer, start) = pacer();
+
+        assert_eq!(pacer.frames_due(start + FRAME), 1);
+        // Oversleeping shortens the next frame instead of pushing every later deadline back
+        assert_eq!(pacer.frames_due(start + FRAME * 2 + Duration::from_millis(3)), 1);
+        assert_eq!(pacer.drift, Duration::from_millis(3));
+        assert_eq!(pacer.next_deadline, start + FRAME * 3);
+    }
+
+    #[test]
+    fn frames_due_should_catch_up_after_a_short_stall() {
+        let (mut pacer, start) = pacer();
+
+        assert_eq!(pacer.frames_due(start + FRAME * 3), 3);
+        assert_eq!(pacer.next_deadline, start + FRAME * 4);
+        assert_eq!(pacer.frames_due(start + FRAME * 4), 1);
+        assert_eq!(pacer.skipped_frames, 0);
+    }
+
+    #[test]
+    fn frames_due_should_drop_frames_after_a_long_stall() {
+        let (mut pacer, start) = pacer();
+
+        let now = start + FRAME * 11;
+        assert_eq!(pacer.frames_due(now), MAX_CATCH_UP_FRAMES);
+        assert_eq!(pacer.skipped_frames, 11 - MAX_CATCH_UP_FRAMES as u64);
+        // The deadlines start over from the end of the stall
+        assert_eq!(pacer.next_deadline, now + FRAME);
+        assert_eq!(pacer.frames_due(now + FRAME), 1);
+    }
+
+    #[test]
+    fn stats_should_cover_each_second_and_then_reset() {
+        let (mut pacer, start) = pacer();
+
+        for frame in 1..100 {
+            pacer.frames_due(start + FRAME * frame);
+        }
+        assert!(pacer.poll_stats().is_none());
+
+        // The hundredth deadline comes two frames late, so it emulates three
+        pacer.frames_due(start + FRAME * 102);
+        let stats = pacer.poll_stats().unwrap();
+        assert!((stats.fps - 100.0 / 1.02).abs() < 1e-9);
+        assert!((stats.speed - 1.0).abs() < 1e-9);
+        assert_eq!(stats.drift, FRAME * 2);
+        assert!(pacer.poll_stats().is_none());
+
+        for frame in 103..203 {
+            pacer.frames_due(start + FRAME * frame);
+        }
+        let stats = pacer.poll_stats().unwrap();
+        assert!((stats.fps - 100.0).abs() < 1e-9);
+        assert!((stats.speed - 1.0).abs() < 1e-9);
+        assert_eq!(stats.skipped_frames, 0);
+    }
+}
@@ -1,61 +1,255 @@
-mod sys_timer;
+mod debug_console;
+mod frame_pacer;
+mod options;
 
-use chip8;
-use chip8::{Chip8, GFX_HEIGHT, GFX_WIDTH};
+use chip8::{Chip8, Chip8Error, Config, Font, FRAME_HZ, GdbServer, Profiler, RewindBuffer, TraceWriter};
 
-use crate::sys_timer::SysTimer;
-use minifb::{Key, Window, WindowOptions};
+use crate::debug_console::DebugConsole;
+use crate::frame_pacer::FramePacer;
+use crate::options::{Options, USAGE};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::rc::Rc;
+use std::{env, fs, process};
 
 const WIDTH: usize = 640;
 const HEIGHT: usize = 360;
+const TITLE: &str = "Chip8.rs - ESC to exit";
+
+// Indexed by the bitplanes set for a pixel; plain CHIP-8 and SUPER-CHIP only use the first two
+const PALETTE: [u32; 4] = [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555];
+
+const SAVE_SLOT_KEYS: [Key; 4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+
+const REWIND_KEY: Key = Key::Backspace;
+// Most frames only change a few bytes, so this holds several minutes of gameplay
+const REWIND_BUDGET: usize = 16 * 1024 * 1024;
 
 // TODO
 // 1. Add test for draw flag in Chip8
 // 2. Add test for read_op_code() func
 
 fn main() {
-    let mut c8 = Chip8::new();
-    c8.load("./roms/TEST_ROM_WITH_AUDIO");
+    let options = Options::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let mut config = options.platform.map_or(Config::default(), |platform| platform.config());
+    config.stack_depth = options.stack_depth.unwrap_or(config.stack_depth);
+    config.stack_policy = options.stack_policy;
+    config.timer_hz = options.timer_hz;
+    if let Some(font) = options.font {
+        // A font without big digits keeps the platform's, so FX30 still works
+        config.font = Font { big: font.big.or(config.font.big), ..font };
+    }
+    config.font_address = options.font_address.unwrap_or(config.font_address);
+    config.load_address = options.load_address.unwrap_or(config.load_address);
+    config.start_address = options.start_address.or(options.load_address).unwrap_or(config.start_address);
+    let mut c8 = Chip8::try_with_config(config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+    match options.seed {
+        Some(seed) => c8.set_seed(seed),
+        None => eprintln!("Random seed {} (pass --seed {} to reproduce this run)", c8.seed(), c8.seed()),
+    }
+
+    let loaded = match options.rom.as_str() {
+        "-" => c8.load_from_reader(io::stdin().lock()),
+        path => c8.load(path),
+    };
+    if let Err(e) = loaded {
+        eprintln!("Failed to load rom: {}", e);
+        process::exit(1);
+    }
+    // A rom from stdin has no path to save next to, so its slots are named after its hash
+    let slot_prefix = match options.rom.as_str() {
+        "-" => format!("{:016x}", c8.rom_hash()),
+        path => path.to_string(),
+    };
+
+    if let Some(path) = &options.trace {
+        let file = File::create(path).unwrap_or_else(|e| {
+            eprintln!("Failed to create {}: {}", path, e);
+            process::exit(1);
+        });
+        c8.set_trace_sink(Some(Box::new(TraceWriter::new(BufWriter::new(file)))));
+    }
 
-    let mut buffer: Vec<u32> = vec![0; GFX_WIDTH * GFX_HEIGHT];
+    let profiler = options.profile.as_ref().map(|_| Rc::new(RefCell::new(Profiler::new())));
+    if let Some(profiler) = &profiler {
+        c8.set_observer(Some(Box::new(profiler.clone())));
+    }
 
-    let mut options = WindowOptions::default();
-    options.resize = true;
+    let mut buffer: Vec<u32> = vec![0; c8.gfx.len()];
+
+    let window_options = WindowOptions {
+        resize: true,
+        ..WindowOptions::default()
+    };
     let mut window =
-        Window::new("Chip8.rs - ESC to exit", WIDTH, HEIGHT, options).unwrap_or_else(|e| {
+        Window::new(TITLE, WIDTH, HEIGHT, window_options).unwrap_or_else(|e| {
             panic!("{}", e);
         });
 
-    let timer = SysTimer::new(16600);
+    // The pacer decides when frames run, so minifb must not throttle updates on its own
+    window.limit_update_rate(None);
+    let mut pacer = FramePacer::new(FRAME_HZ);
+    let mut running = true;
+    let mut rewind = RewindBuffer::new(REWIND_BUDGET);
+    rewind.record(&c8);
+    let mut console = options.debug.then(|| DebugConsole::new(&c8));
+    let mut gdb = options.gdb_port.map(|port| {
+        let server = GdbServer::bind(("127.0.0.1", port)).unwrap_or_else(|e| {
+            eprintln!("Failed to listen for gdb on port {}: {}", port, e);
+            process::exit(1);
+        });
+        eprintln!("Waiting for gdb, attach with: target remote localhost:{}", port);
+        server
+    });
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        timer.pause_until_target_reached();
-        c8.tick();
+        let frames = pacer.wait_for_next_frame();
 
         update_input_states(&mut c8, &mut window);
 
-        if c8.is_draw_ready() {
+        let mut state_loaded = handle_save_slots(&mut c8, &window, &slot_prefix);
+        if state_loaded {
+            running = true;
+            rewind.clear();
+            rewind.record(&c8);
+        }
+
+        if window.is_key_down(REWIND_KEY) {
+            // Step back one recorded frame per frame the emulation would have run
+            for _ in 0..frames {
+                match rewind.step_back(&mut c8) {
+                    Ok(true) => {
+                        state_loaded = true;
+                        running = true;
+                    }
+                    Ok(false) => break,
+                    Err(e) => {
+                        eprintln!("Failed to rewind: {}", e);
+                        break;
+                    }
+                }
+            }
+        } else {
+            // A faulting rom stops the emulation but keeps the last frame on screen
+            for _ in 0..frames {
+                if !running {
+                    break;
+                }
+                if let Some(console) = console.as_mut() {
+                    // The console reports faults itself and pauses instead of stopping
+                    if console.run_frame(&mut c8, options.cycles_per_frame) {
+                        rewind.record(&c8);
+                    }
+                    continue;
+                }
+                if let Some(server) = gdb.as_mut() {
+                    if server.run_frame(&mut c8, options.cycles_per_frame) {
+                        rewind.record(&c8);
+                    }
+                    if let Some(e) = server.take_error() {
+                        eprintln!("gdb connection lost: {}", e);
+                    }
+                    continue;
+                }
+                match c8.run_frame(options.cycles_per_frame) {
+                    Ok(()) => {
+                        rewind.record(&c8);
+                        if let Some(reason) = c8.halt_reason() {
+                            eprintln!("Emulation halted: {}", reason);
+                            running = false;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Emulation stopped: {}", e);
+                        running = false;
+                    }
+                }
+            }
+        }
+
+        if let Some(stats) = pacer.poll_stats() {
+            window.set_title(&format!(
+                "{} - {:.1} fps, {:.0}% speed, {:.1} ms drift, {} skipped",
+                TITLE,
+                stats.fps,
+                stats.speed * 100.0,
+                stats.drift.as_secs_f64() * 1000.0,
+                stats.skipped_frames
+            ));
+        }
+
+        if state_loaded || c8.is_draw_ready() {
             copy_gfx_to_pixel_buffer(&mut c8, &mut buffer);
         }
 
         // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
         window
-            .update_with_buffer(&buffer, GFX_WIDTH, GFX_HEIGHT)
+            .update_with_buffer(&buffer, c8.display_width(), c8.display_height())
             .unwrap();
     }
+
+    if let (Some(path), Some(profiler)) = (&options.profile, profiler) {
+        write_profile(path, &profiler.borrow());
+    }
+}
+
+fn write_profile(path: &str, profiler: &Profiler) {
+    let report = profiler.report();
+    let contents = if path.ends_with(".json") { report.to_json() } else { report.to_string() };
+    match fs::write(path, contents) {
+        Ok(()) => eprintln!("Wrote profile to {}", path),
+        Err(e) => eprintln!("Failed to write {}: {}", path, e),
+    }
 }
 
 fn copy_gfx_to_pixel_buffer(c8: &mut Chip8, buffer: &mut Vec<u32>) {
-    for col in 0..chip8::GFX_HEIGHT {
-        for row in 0..chip8::GFX_WIDTH {
-            let index = col * chip8::GFX_WIDTH + row;
-            if c8.gfx[index] == 0 {
-                buffer[index] = 0x000000;
-            } else {
-                buffer[index] = 0xFFFFFF;
+    // The rom may have switched between lores and hires since the last frame
+    buffer.resize(c8.gfx.len(), 0);
+
+    for (index, pixel) in c8.gfx.iter().enumerate() {
+        buffer[index] = PALETTE[(*pixel & 0b11) as usize];
+    }
+}
+
+// F1-F4 load a save slot and Shift+F1-F4 save into it. Returns true when a state was loaded.
+fn handle_save_slots(c8: &mut Chip8, window: &Window, slot_prefix: &str) -> bool {
+    let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+
+    for (index, key) in SAVE_SLOT_KEYS.iter().enumerate() {
+        if !window.is_key_pressed(*key, KeyRepeat::No) {
+            continue;
+        }
+
+        let path = format!("{}.state{}", slot_prefix, index + 1);
+        if shift {
+            match fs::write(&path, c8.save_state()) {
+                Ok(()) => eprintln!("Saved {}", path),
+                Err(e) => eprintln!("Failed to save {}: {}", path, e),
+            }
+        } else {
+            let result = fs::read(&path)
+                .map_err(Chip8Error::from)
+                .and_then(|data| c8.load_state(&data));
+            match result {
+                Ok(()) => {
+                    eprintln!("Loaded {}", path);
+                    return true;
+                }
+                Err(e) => eprintln!("Failed to load {}: {}", path, e),
             }
         }
     }
+
+    false
 }
 
 fn update_input_states(c8: &mut Chip8, window: &mut Window) {
@@ -0,0 +1,248 @@
+use chip8::{Font, FontSet, Platform, StackPolicy, FRAME_HZ};
+
+const DEFAULT_ROM: &str = "./roms/TEST_ROM_WITH_AUDIO";
+// Without --platform the machine is Config::default(), which runs SUPER-CHIP 1.1 roms
+const DEFAULT_PLATFORM: Platform = Platform::SChip11;
+
+pub const USAGE: &str = "usage: main [--platform NAME] [--ipf N] [--timer-hz HZ] [--seed N] [--stack-depth N]
+            [--stack-policy POLICY] [--font NAME|FILE] [--font-address ADDR] [--load-address ADDR]
+            [--start-address ADDR] [--debug | --gdb PORT] [--trace FILE] [--profile FILE] [ROM]
+
+ROM is a file, or - to read the rom from stdin.
+
+options:
+    --platform NAME    vip, chip48, schip10, schip11, xochip or octo
+    --ipf N            instructions executed per frame, defaults to the platform's speed
+    --timer-hz HZ      rate the delay and sound timers count down at, 60 by default. Frames
+                       always run at 60 Hz, so this doesn't change the instruction rate
+    --seed N           seed for the random number generator, to reproduce a previous run
+    --stack-depth N    return addresses the stack holds, defaults to the platform's depth
+    --stack-policy P   what a CALL on a full stack or a RET on an empty one does: error
+                       (stop the emulator), halt (freeze the rom) or wrap (ring buffer)
+    --font NAME|FILE   vip, dream6800, eti660, fishnchips, chip48, schip or octo, or a file
+                       of 80 bytes of 4x5 digits optionally followed by 160 of 8x10 digits
+    --font-address A   where the font is loaded, in hex, 0 by default
+    --load-address A   where the rom is loaded, in hex, 200 by default and 600 for ETI-660 roms
+    --start-address A  where execution starts, in hex, the load address by default
+    --debug            start paused and read debugger commands from stdin
+    --gdb PORT         start paused and wait for gdb to attach on localhost:PORT
+    --trace FILE       log every executed instruction to FILE, see chip8-tracediff
+    --profile FILE     count executed instructions and write a report to FILE on exit,
+                       as JSON when FILE ends in .json and as text otherwise";
+
+pub struct Options {
+    pub rom: String,
+    pub platform: Option<Platform>,
+    pub cycles_per_frame: u32,
+    pub timer_hz: u32,
+    pub seed: Option<u64>,
+    pub stack_depth: Option<usize>,
+    pub stack_policy: StackPolicy,
+    pub font: Option<Font>,
+    pub font_address: Option<u16>,
+    pub load_address: Option<u16>,
+    pub start_address: Option<u16>,
+    pub debug: bool,
+    pub gdb_port: Option<u16>,
+    pub trace: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl Options {
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options {
+            rom: DEFAULT_ROM.to_string(),
+            platform: None,
+            cycles_per_frame: DEFAULT_PLATFORM.cycles_per_frame(),
+            timer_hz: FRAME_HZ,
+            seed: None,
+            stack_depth: None,
+            stack_policy: StackPolicy::default(),
+            font: None,
+            font_address: None,
+            load_address: None,
+            start_address: None,
+            debug: false,
+            gdb_port: None,
+            trace: None,
+            profile: None,
+        };
+        let mut cycles_per_frame = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--platform" => {
+                    let name = args.next().ok_or("--platform needs a value")?;
+                    options.platform = Some(name.parse()?);
+                }
+                "--ipf" => cycles_per_frame = Some(parse_number(&arg, args.next())?),
+                "--timer-hz" => options.timer_hz = parse_number(&arg, args.next())?,
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    let seed = value.parse().map_err(|_| format!("--seed expects a number, got '{}'", value))?;
+                    options.seed = Some(seed);
+                }
+                "--stack-depth" => {
+                    let value = args.next().ok_or("--stack-depth needs a value")?;
+                    let depth = match value.parse::<u16>() {
+                        Ok(depth) if depth > 0 => depth,
+                        _ => return Err(format!("--stack-depth expects a number from 1 to 65535, got '{}'", value)),
+                    };
+                    options.stack_depth = Some(depth as usize);
+                }
+                "--stack-policy" => {
+                    let name = args.next().ok_or("--stack-policy needs a value")?;
+                    options.stack_policy = name.parse()?;
+                }
+                "--font" => {
+                    let value = args.next().ok_or("--font needs a name or a file")?;
+                    let font = match value.parse::<FontSet>() {
+                        Ok(font_set) => font_set.font(),
+                        Err(_) => Font::load(&value).map_err(|e| format!("Failed to load font {}: {}", value, e))?,
+                    };
+                    options.font = Some(font);
+                }
+                "--font-address" => options.font_address = Some(parse_address(&arg, args.next())?),
+                "--load-address" => options.load_address = Some(parse_address(&arg, args.next())?),
+                "--start-address" => options.start_address = Some(parse_address(&arg, args.next())?),
+                "--debug" => options.debug = true,
+                "--gdb" => {
+                    let value = args.next().ok_or("--gdb needs a port")?;
+                    let port = value.parse().map_err(|_| format!("--gdb expects a port number, got '{}'", value))?;
+                    options.gdb_port = Some(port);
+                }
+                "--trace" => options.trace = Some(args.next().ok_or("--trace needs a file")?),
+                "--profile" => options.profile = Some(args.next().ok_or("--profile needs a file")?),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+                // A lone dash reads the rom from stdin
+                _ => options.rom = arg,
+            }
+        }
+
+        if options.debug && options.gdb_port.is_some() {
+            return Err("--debug and --gdb can't be used together".to_string());
+        }
+        // The debug console reads its commands from stdin, which the rom would already have used up
+        if options.debug && options.rom == "-" {
+            return Err("--debug can't be used with a rom read from stdin".to_string());
+        }
+        let platform = options.platform.unwrap_or(DEFAULT_PLATFORM);
+        options.cycles_per_frame = cycles_per_frame.unwrap_or(platform.cycles_per_frame());
+
+        Ok(options)
+    }
+}
+
+fn parse_address(option: &str, value: Option<String>) -> Result<u16, String> {
+    let value = value.ok_or(format!("{} needs a value", option))?;
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("{} expects a hex address, got '{}'", option, value))
+}
+
+fn parse_number(option: &str, value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or(format!("{} needs a value", option))?;
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(format!("{} expects a positive number, got '{}'", option, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_should_use_the_defaults_without_options() {
+        let options = parse(&[]).unwrap();
+
+        assert_eq!(options.rom, DEFAULT_ROM);
+        assert_eq!(options.platform, None);
+        assert_eq!(options.cycles_per_frame, Platform::SChip11.cycles_per_frame());
+        assert_eq!(options.timer_hz, FRAME_HZ);
+        assert_eq!(options.stack_policy, StackPolicy::Error);
+        assert!(options.seed.is_none() && options.stack_depth.is_none() && options.font.is_none());
+        assert!(!options.debug && options.gdb_port.is_none());
+    }
+
+    #[test]
+    fn parse_should_read_every_option() {
+        let args = [
+            "--platform", "schip11", "--ipf", "100", "--timer-hz", "50", "--seed", "42", "--stack-depth", "12",
+            "--stack-policy", "wrap", "--font", "eti660", "--font-address", "0x050", "--load-address", "600",
+            "--start-address", "0x602", "--gdb", "9000", "--trace", "out.log", "--profile", "out.json", "game.ch8",
+        ];
+        let options = parse(&args).unwrap();
+
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.platform, Some(Platform::SChip11));
+        assert_eq!(options.cycles_per_frame, 100);
+        assert_eq!(options.timer_hz, 50);
+        assert_eq!(options.seed, Some(42));
+        assert_eq!(options.stack_depth, Some(12));
+        assert_eq!(options.stack_policy, StackPolicy::Wrap);
+        assert_eq!(options.font, Some(FontSet::Eti660.font()));
+        assert_eq!((options.font_address, options.load_address, options.start_address), (Some(0x50), Some(0x600), Some(0x602)));
+        assert_eq!(options.gdb_port, Some(9000));
+        assert_eq!(options.trace.as_deref(), Some("out.log"));
+        assert_eq!(options.profile.as_deref(), Some("out.json"));
+    }
+
+    #[test]
+    fn parse_should_take_the_speed_from_the_platform_unless_ipf_is_given() {
+        assert_eq!(parse(&["--platform", "xochip"]).unwrap().cycles_per_frame, 1000);
+        assert_eq!(parse(&["--ipf", "7", "--platform", "xochip"]).unwrap().cycles_per_frame, 7);
+        // The timers keep their own rate whatever the speed
+        assert_eq!(parse(&["--platform", "xochip"]).unwrap().timer_hz, FRAME_HZ);
+    }
+
+    #[test]
+    fn parse_should_report_missing_values() {
+        for option in [
+            "--platform", "--ipf", "--timer-hz", "--seed", "--stack-depth", "--stack-policy", "--font",
+            "--font-address", "--load-address", "--start-address", "--gdb", "--trace", "--profile",
+        ] {
+            let error = parse(&[option]).err().unwrap();
+            assert!(error.starts_with(option), "{}: {}", option, error);
+        }
+    }
+
+    #[test]
+    fn parse_should_reject_invalid_values() {
+        let invalid = [
+            ["--platform", "gameboy"],
+            ["--ipf", "0"],
+            ["--ipf", "fast"],
+            ["--timer-hz", "0"],
+            ["--seed", "-1"],
+            ["--stack-depth", "0"],
+            ["--stack-depth", "65536"],
+            ["--stack-policy", "ignore"],
+            ["--font", "./this/font/does/not/exist"],
+            ["--font-address", "0x10000"],
+            ["--load-address", "zz"],
+            ["--start-address", ""],
+            ["--gdb", "port"],
+        ];
+        for args in invalid {
+            assert!(parse(&args).is_err(), "{:?}", args);
+        }
+        assert_eq!(parse(&["--fast"]).err().unwrap(), "unknown option --fast");
+        assert!(parse(&["--debug", "--gdb", "9000"]).is_err());
+    }
+
+    #[test]
+    fn parse_should_accept_a_dash_for_stdin() {
+        assert_eq!(parse(&["-"]).unwrap().rom, "-");
+        assert_eq!(parse(&["--gdb", "9000", "-"]).unwrap().rom, "-");
+    }
+
+    #[test]
+    fn parse_should_reject_the_debug_console_with_a_rom_from_stdin() {
+        assert!(parse(&["--debug", "-"]).is_err());
+        assert!(parse(&["--debug", "game.ch8"]).is_ok());
+    }
+}
@@ -0,0 +1,139 @@
+use chip8::TraceEntry;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::{env, process};
+
+const USAGE: &str = "usage: chip8-tracediff [--context N] EXPECTED ACTUAL
+
+Compares two traces written by main --trace and reports the first instruction where
+they disagree, ignoring the mnemonic column. Exits with 1 when the traces differ.
+
+options:
+    --context N    matching instructions to show before the divergence, 5 by default";
+
+const DEFAULT_CONTEXT: usize = 5;
+
+struct Options {
+    expected: String,
+    actual: String,
+    context: usize,
+}
+
+struct Trace {
+    path: String,
+    lines: Lines<BufReader<File>>,
+    line: usize,
+}
+
+impl Trace {
+    fn open(path: &str) -> Trace {
+        let file = File::open(path).unwrap_or_else(|e| {
+            eprintln!("Failed to read {}: {}", path, e);
+            process::exit(2);
+        });
+        Trace { path: path.to_string(), lines: BufReader::new(file).lines(), line: 0 }
+    }
+
+    // Skips blank lines, so a trailing newline or hand edited trace still lines up
+    fn next_entry(&mut self) -> Option<TraceEntry> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to read {}: {}", self.path, e);
+                    process::exit(2);
+                }
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(line.parse().unwrap_or_else(|e| {
+                eprintln!("{}:{}: {}", self.path, self.line, e);
+                process::exit(2);
+            }));
+        }
+    }
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let mut expected = Trace::open(&options.expected);
+    let mut actual = Trace::open(&options.actual);
+    let mut context = VecDeque::with_capacity(options.context + 1);
+    let mut count = 0;
+
+    loop {
+        let entry = match (expected.next_entry(), actual.next_entry()) {
+            (None, None) => {
+                println!("Traces match over {} instructions", count);
+                return;
+            }
+            (Some(left), Some(right)) if left.differences(&right).is_empty() => left,
+            (left, right) => {
+                report(&options, &context, count, left, right);
+                process::exit(1);
+            }
+        };
+        context.push_back(entry);
+        if context.len() > options.context {
+            context.pop_front();
+        }
+        count += 1;
+    }
+}
+
+fn report(
+    options: &Options,
+    context: &VecDeque<TraceEntry>,
+    count: usize,
+    expected: Option<TraceEntry>,
+    actual: Option<TraceEntry>,
+) {
+    match (&expected, &actual) {
+        (Some(left), Some(right)) => println!(
+            "Traces diverge at instruction {}, in {}",
+            count + 1,
+            left.differences(right).join(", ")
+        ),
+        (None, _) => println!("{} ends after {} instructions", options.expected, count),
+        (_, None) => println!("{} ends after {} instructions", options.actual, count),
+    }
+
+    println!("--- {}\n+++ {}", options.expected, options.actual);
+    for entry in context {
+        println!("  {}", entry);
+    }
+    if let Some(entry) = expected {
+        println!("- {}", entry);
+    }
+    if let Some(entry) = actual {
+        println!("+ {}", entry);
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut paths = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let value = args.next().ok_or("--context needs a value")?;
+                context = value.parse().map_err(|_| format!("--context expects a number, got '{}'", value))?;
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => paths.push(arg),
+        }
+    }
+
+    match <[String; 2]>::try_from(paths) {
+        Ok([expected, actual]) => Ok(Options { expected, actual, context }),
+        Err(_) => Err("expected two traces".to_string()),
+    }
+}